@@ -1,5 +1,6 @@
 use std::fs::File;
 use std::io::Write;
+use std::time::Duration;
 
 use config::Config;
 use once_cell::sync::Lazy;
@@ -7,8 +8,16 @@ use once_cell::sync::Lazy;
 pub const fn config_setup() -> Lazy<Config> {
     Lazy::new(|| {
         let args: Vec<String> = std::env::args().collect();
+        let is_test_parser = args.get(1).is_some_and(|arg| arg == "test-parser");
 
-        let path = if args.len() > 1 {
+        let path = if is_test_parser {
+            // logpeek-server test-parser --config config.toml --app NAME FILE
+            args.iter()
+                .skip_while(|arg| *arg != "--config")
+                .nth(1)
+                .cloned()
+                .unwrap_or_else(|| "config.toml".to_string())
+        } else if args.len() > 1 {
             args[1].clone()
         } else {
             "config.toml".to_string()
@@ -20,12 +29,15 @@ pub const fn config_setup() -> Lazy<Config> {
             }
         }
 
-        if let Ok(mut file) = File::create_new(&path) {
-            // Logger is not initialized yet, so we can't use log macros
-            println!("No config file found, creating default config {}", path);
+        // Testing a parser shouldn't leave a new config file behind
+        if !is_test_parser {
+            if let Ok(mut file) = File::create_new(&path) {
+                // Logger is not initialized yet, so we can't use log macros
+                println!("No config file found, creating default config {}", path);
 
-            file.write_all(DEFAULT_CONFIG.as_bytes())
-                .expect("Failed to write default config");
+                file.write_all(DEFAULT_CONFIG.as_bytes())
+                    .expect("Failed to write default config");
+            }
         }
 
         Config::builder()
@@ -36,6 +48,56 @@ pub const fn config_setup() -> Lazy<Config> {
     })
 }
 
+// Parses sizes like "200MiB", "1.5 GB" or 1048576 into bytes. KB, MB and GB are powers of 1000, KiB, MiB and GiB of 1024.
+pub fn parse_byte_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let unit_start = size
+        .find(|c: char| !c.is_ascii_digit() && c != '.' && c != '_')
+        .unwrap_or(size.len());
+
+    let number: f64 = size[..unit_start].replace('_', "").parse().ok()?;
+    let multiplier: u64 = match size[unit_start..].trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        "kib" => 1 << 10,
+        "mib" => 1 << 20,
+        "gib" => 1 << 30,
+        "tib" => 1 << 40,
+        _ => return None,
+    };
+
+    Some((number * multiplier as f64) as u64)
+}
+
+// Durations are compared with the timestamps of the entries, which are kept in nanoseconds since the Unix epoch
+const MAX_DURATION_SECS: u64 = i64::MAX as u64 / 1_000_000_000;
+
+// Parses durations like "90s", "30m", "12h", "7d" or "2w". Longer durations than the timestamps can cover are rejected.
+pub fn parse_duration(duration: &str) -> Option<Duration> {
+    let duration = duration.trim();
+    let unit_start = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration.len());
+
+    let number: u64 = duration[..unit_start].parse().ok()?;
+    let seconds: u64 = match duration[unit_start..].trim() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+
+    number
+        .checked_mul(seconds)
+        .filter(|seconds| *seconds <= MAX_DURATION_SECS)
+        .map(Duration::from_secs)
+}
+
 const DEFAULT_CONFIG: &str = r#"# Config file for logpeek-server. Place this file in the same directory as the server executable.
 # The values in this example config are the defaults.
 
@@ -51,9 +113,17 @@ address = "127.0.0.1:3001"
 # The maximum number of login attempts before the server is locked. Cleared by manual server restart.
 max_login_attempts = 3
 
-# Minimum amount of time in seconds between log buffer updates. Triggered by ANY request to the server.
-# Force-refresh button in the frontend bypasses this.
-buffer_update_cooldown = 10
+# Log files are watched for changes and new entries are read in as they are written.
+# Time in milliseconds to wait for further changes before updating the log buffer, so bursts of writes are read in one go.
+watcher_debounce = 500
+
+# The maximum memory taken up by the log entries of all applications together, e.g. "512MiB" or "2GB".
+# When it is exceeded, the oldest entries across all applications are discarded first. Not limited by default.
+#max_memory = "1GiB"
+
+# Parsed log entries are also written to this directory, so that they are loaded from there after a restart instead of
+# parsing the log files again. Entries are kept as long as they fit into their buffer, even after their log files are deleted.
+#store_path = "logpeek-store"
 
 
 [main.logger]
@@ -70,32 +140,183 @@ log_path = "logpeek-logs"
 log_file_name = "logpeek-server.log"
 
 
-# List of applications whose log files will be monitored. Each application must have a path, a respective parser, the timeformat and optional buffer_size.
+# List of applications whose log files will be monitored. Each application must have a path, a respective parser, the timeformat and optional buffer_size or buffer_bytes.
 [[application]]
 # Path to the application's log folder or a single logfile. The path can be relative to the server executable or an absolute path. Nested subfolders are supported.
-# Make sure that this path is both accessible and does not contain any other files besides log files. The file extension doesn't matter.
+# Make sure that this path is accessible. Unless include or exclude is set below, all files in it are read as log files.
+# Rotated log files compressed with gzip, zstd or xz are decompressed automatically.
+# The path can be left out for applications that only receive logs through the ingest endpoint, the name is then required.
 path = "logpeek-logs"
 
+# Glob patterns that select which files in the path are read. Patterns without a slash are matched against the file name,
+# patterns with one against the path relative to the application's path. When include is set, only matching files are read.
+# Excluded files are never read, even if they are also included.
+#include = ["*.log", "*.log.*"]
+#exclude = ["*.pid", "*.lock", "archive/**"]
+
 # The name of the application. If omitted, the path will be used as the name instead.
+# Logs can also be pushed to an application with POST /api/ingest/<name>. Plain text bodies are parsed line by line
+# like the log files, while application/x-ndjson bodies contain one pre-parsed entry per line,
+# e.g. {"timestamp": "2024-01-17T13:55:30Z", "level": "WARN", "module": "backup", "message": "...", "fields": {"host": "a"}}.
+# Only the message is required. When main.secret is set, the endpoint requires the same basic authentication as the UI.
 name = "logpeek-server"
 
 # The regular expression used to parse the logs files of this application.
-# All 4 possible named capture groups are present in the example below. Only the message capture group is required.
+# All 4 standard named capture groups are present in the example below. Only the message capture group is required.
+# Any other named capture groups, e.g. (?P<status>\d+), are kept as additional fields that can be filtered by.
 parser = '''^(?P<timestamp>\S+) (?P<level>\S+) (?P<module>\S+) - (?P<message>.+)$''' # This example corresponds to the logs generated by logpeek-server itself
 #parser = '''^(?P<message>.+)$''' # This is the minimal example, use this if you don't care about filtering by other fields.
 
-# The format of the timestamp in the logs. Supported formats are "iso8601", "rfc3339", "rfc2822" and custom.
+# The parser can be tried out against sample lines without restarting the server:
+# logpeek-server test-parser --config config.toml --app <name> sample.log
+# Alternatively, POST /api/parser_test with {"application": "<name>", "input": "..."} or, to try out settings before adding them
+# to this file, {"config": {"parser": "...", "timeformat": "..."}, "input": "..."}.
+
+# Instead of writing the parser, one of the built-in presets can be used. A preset sets the parser, timeformat and
+# level_map (and multiline for formats with stack traces), any of which can still be overridden above or below.
+# Available presets: "logpeek", "nginx_combined", "nginx_error", "apache_combined", "apache_error", "env_logger",
+# "tracing", "python", "python_asctime", "log4j", "postgresql", "syslog" and "syslog_rfc3339".
+#preset = "nginx_combined"
+
+# The format of the log lines, either "regex" (default), "json", "logfmt", "journal_json", "journal_export", "docker" or "cri".
+# With "json", each line is parsed as a JSON object and the parser field is not needed. The fields below select which
+# JSON fields are used for the log entry. Nested fields can be selected with dots, e.g. "log.level".
+# With "logfmt", lines like `ts=... level=warn msg="..." module=db` are parsed. The defaults for the fields below are
+# "ts", "level", "module" and "msg" instead. All the other key/value pairs are kept and can be filtered by.
+# "journal_json" and "journal_export" read systemd journal entries as written by `journalctl -o json` and
+# `journalctl -o export`, e.g. `journalctl -f -o export > journal/system.export`. PRIORITY is used as the level,
+# SYSLOG_IDENTIFIER or _SYSTEMD_UNIT as the module and __REALTIME_TIMESTAMP as the timestamp, so no other fields are needed.
+# "docker" reads container logs of Docker's json-file driver (/var/lib/docker/containers/*/*-json.log) and "cri" the
+# Kubernetes container logs under /var/log/pods. The envelope is removed and lines split by the runtime are joined again.
+# The parser field is optional for these and is applied to the inner message, the stream is kept as a field.
+#format = "json"
+#timestamp_field = "timestamp"
+#level_field = "level"
+#module_field = "module"
+#message_field = "message" # Is required to be present in each line
+
+# The character encoding of the log files, either "utf-8" (default), "utf-16le", "utf-16be", "latin1" or "windows-1252".
+# Lines that are not valid in the encoding are dropped, unless lossy is enabled which replaces the invalid bytes instead.
+#encoding = "utf-8"
+#lossy = false
+
+# The format of the timestamp in the logs. Supported formats are "iso8601", "rfc3339", "rfc2822", "epoch_s", "epoch_ms",
+# "epoch_ns" and custom. The epoch formats are Unix timestamps in seconds, milliseconds or nanoseconds, e.g. "1705499736.123".
 # Altough custom timeformats are supported, built-in formats should be preferred.
-# Since UTC is used internally, the timeformat must include an offset. Minimum example for a custom timeformat: "[year]-[month]-[day]|[hour]|[offset_hour]"
+# Minimum example for a custom timeformat: "[year]-[month]-[day]|[hour]"
+# When the year is missing, e.g. syslog's "[month repr:short] [day padding:space] [hour]:[minute]:[second]", the current
+# year is assumed, or the previous one if the timestamp would otherwise be in the future.
 # Refer to https://time-rs.github.io/book/api/format-description.html (version 1) for possible options.
 timeformat = "iso8601"
 
+# The IANA timezone of timestamps without an offset, e.g. "Europe/Tallinn". Daylight saving time is taken into account.
+# Timestamps that include an offset are not affected. Defaults to UTC.
+#timezone = "Europe/Tallinn"
+
 # The size of the log buffer that is kept in memory. When the buffer is full, the oldest logs are discarded first.
 buffer_size = 1_000_000
 
+# Limits the log buffer by the memory its entries take up instead, e.g. "200MiB". KB, MB and GB are powers of 1000,
+# KiB, MiB and GiB powers of 1024. When only buffer_bytes is set, the number of entries is not limited.
+#buffer_bytes = "200MiB"
+
+# Entries older than this are discarded as well, e.g. "12h", "7d" or "4w". Files that were last modified before
+# the retention window are not read at startup. Not limited by default.
+#retention = "7d"
+
 # Allows mapping custom log levels to the standard log levels. Possible values are "ERROR", "WARN", "INFO", "DEBUG" and "TRACE".
 # The keys are case-sensitive!
 #level_map = { "CRITICAL" = "ERROR", "CATASTROPHE" = "error" }
 
+# Joins log entries that span multiple lines, such as stack traces, into a single entry.
+# "unmatched" treats every line that doesn't match the parser as a continuation of the previous entry.
+# Alternatively, a regular expression matching the continuation lines can be given instead.
+#multiline = "unmatched"
+#multiline = '''^\s+''' # Lines starting with whitespace continue the previous entry
+
+# The maximum size of a multiline message in bytes. Continuation lines beyond this are dropped.
+#multiline_max_size = 65_536
+
+# What to do with lines that couldn't be parsed. "drop" (default) discards them and logs the error.
+# "keep_raw" keeps the whole line as the message of an entry that is flagged as unparsed, these can be filtered with parse_failed=true.
+# "append_to_previous" appends the line to the previous entry and falls back to "keep_raw" if there is none.
+#on_parse_error = "keep_raw"
+# Either way, parse errors are counted per file and can be checked with GET /api/ingest_stats together with sample lines.
+
 # There is no limit to the amount of applications that can be monitored at once.
-# Simply add another [[application]] section with the same fields as above."#;
+# Simply add another [[application]] section with the same fields as above.
+
+
+# Optional templates for many similar applications, e.g. one log directory per service.
+# An application is created for every path matching the {name} placeholder, also for directories that appear later on.
+# All the other fields of [[application]] can be used and are shared by the created applications.
+#[[application_template]]
+# With a trailing slash only directories match, otherwise files do as well, e.g. "/var/log/services/{name}.log".
+# Paths whose application name is already taken are skipped.
+#path = "/var/log/services/{name}/"
+
+# The name of the created applications, {name} is replaced with the matched part of the path. Defaults to "{name}".
+#name = "service-{name}"
+#parser = '''^(?P<timestamp>\S+) (?P<level>\S+) (?P<module>\S+) - (?P<message>.+)$'''
+#buffer_size = 100_000
+
+
+# Optional syslog endpoints that receive RFC 5424 and RFC 3164 messages over the network or a Unix socket.
+# The received log entries are shown under their own application. Multiple listeners can be configured.
+#[[listener]]
+# Either "udp" (default), "tcp" or "unix". TCP supports both octet counted and newline delimited messages.
+#protocol = "udp"
+
+# The address to listen on, or the path of the socket file when using "unix".
+#address = "0.0.0.0:514"
+
+# The name of the application that the received log entries are shown under.
+#name = "syslog"
+
+# What to do with messages that couldn't be parsed, the same as on_parse_error of an application.
+# Their parse errors are listed under "syslog" by GET /api/ingest_stats.
+#on_parse_error = "keep_raw"
+
+# The size of the log buffer for the received log entries, buffer_bytes can be used as well.
+#buffer_size = 1_000_000"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90s"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration(" 30m "), Some(Duration::from_secs(30 * 60)));
+        assert_eq!(
+            parse_duration("12 h"),
+            Some(Duration::from_secs(12 * 60 * 60))
+        );
+        assert_eq!(
+            parse_duration("7d"),
+            Some(Duration::from_secs(7 * 24 * 60 * 60))
+        );
+        assert_eq!(
+            parse_duration("2w"),
+            Some(Duration::from_secs(14 * 24 * 60 * 60))
+        );
+    }
+
+    #[test]
+    fn durations_out_of_range() {
+        assert_eq!(parse_duration("100000000w"), None);
+        assert_eq!(parse_duration("18446744073709551615s"), None);
+        assert_eq!(parse_duration("99999999999999999999s"), None);
+
+        // About 292 years is the longest duration that fits
+        assert!(parse_duration("15000w").is_some());
+        assert_eq!(parse_duration("16000w"), None);
+    }
+
+    #[test]
+    fn durations_malformed() {
+        for duration in ["", "7", "d", "7x", "-7d", "1.5h", "7 days"] {
+            assert_eq!(parse_duration(duration), None, "{}", duration);
+        }
+    }
+}
@@ -1,21 +1,26 @@
 mod config;
+mod listener;
+mod log_buffer;
 mod log_reader;
 mod middleware;
 mod routes;
 
 use crate::config::config_setup;
+use crate::log_buffer::{LogBuffer, LogEntryRef};
+use crate::log_reader::application::Application;
+use crate::log_reader::stats::ParseStats;
 use ::config::Config;
 use axum::Router;
 use log::{info, LevelFilter};
 use logpeek::config::LoggingMode;
 use once_cell::sync::Lazy;
-use ringbuffer::{AllocRingBuffer, RingBuffer};
 use routes::router_setup;
 use serde::Serialize;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use std::time::SystemTime;
 use sysinfo::{CpuRefreshKind, MemoryRefreshKind, System};
+use time::format_description::well_known::Rfc3339;
 use time::OffsetDateTime;
 use tokio::signal;
 use tokio::sync::{Mutex, MutexGuard, RwLock};
@@ -27,15 +32,23 @@ struct LogEntry {
     level: log::Level,
     module: String,
     message: String,
+    // Additional key/value pairs of structured log formats
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    fields: BTreeMap<String, String>,
     application: usize,
+    // Set for lines that were kept as is because they couldn't be parsed
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    parse_failed: bool,
 }
 
 #[derive(Clone)]
 struct SharedState {
-    log_buffer: Arc<RwLock<HashMap<usize, AllocRingBuffer<LogEntry>>>>,
-    cache: Arc<Mutex<HashMap<String, (SystemTime, usize)>>>,
+    log_buffer: Arc<RwLock<HashMap<usize, LogBuffer>>>,
     i_to_app: Arc<Mutex<HashMap<usize, String>>>,
-    last_buffer_update: Arc<Mutex<SystemTime>>,
+    // Parse errors per application index and file path
+    parse_stats: Arc<Mutex<HashMap<usize, BTreeMap<String, ParseStats>>>>,
+    // Applications by name, including those of templates found so far
+    applications: Arc<Mutex<HashMap<String, Arc<Application>>>>,
     sys: Arc<Mutex<System>>,
     server_start_time: Arc<SystemTime>,
     os: Arc<String>,
@@ -80,8 +93,24 @@ pub async fn run() {
 
     //Read in and process the log entries
     let log_buffer = Arc::new(RwLock::new(HashMap::new()));
-    let cache = Arc::new(Mutex::new(HashMap::new()));
+    let cache = Arc::new(Mutex::new(log_reader::load_file_states()));
     let i_to_app = Arc::new(Mutex::new(HashMap::new()));
+    let parse_stats = Arc::new(Mutex::new(HashMap::new()));
+
+    // Applications are parsed once, those of templates are added by the watcher as their paths appear
+    let templates =
+        log_reader::application::get_templates().unwrap_or_else(|err| panic!("{:#}", err));
+    let app_list: Vec<Arc<Application>> = log_reader::application::get_applications(&templates)
+        .unwrap_or_else(|err| panic!("{:#}", err))
+        .into_iter()
+        .map(Arc::new)
+        .collect();
+    let applications = Arc::new(Mutex::new(
+        app_list
+            .iter()
+            .map(|app| (app.name.clone(), app.clone()))
+            .collect::<HashMap<_, _>>(),
+    ));
 
     // Initialize the system info
     let sys = Arc::new(Mutex::new(System::new_with_specifics(
@@ -97,6 +126,9 @@ pub async fn run() {
         log_buffer.clone(),
         cache.clone(),
         i_to_app.clone(),
+        parse_stats.clone(),
+        &app_list,
+        None,
         sys.clone(),
         true,
     )
@@ -104,8 +136,8 @@ pub async fn run() {
     let loaded_count = log_buffer
         .read()
         .await
-        .iter()
-        .map(|(_, buffer)| buffer.len())
+        .values()
+        .map(|buffer| buffer.len())
         .sum::<usize>();
     info!(
         "Loaded {} log entries for {} applications in {:?}",
@@ -114,11 +146,31 @@ pub async fn run() {
         load_start.elapsed().unwrap()
     );
 
+    listener::start_listeners(
+        log_buffer.clone(),
+        i_to_app.clone(),
+        parse_stats.clone(),
+        sys.clone(),
+    )
+    .await;
+
+    tokio::spawn(log_buffer::expire_entries(log_buffer.clone()));
+
+    tokio::spawn(log_reader::watcher::watch_logs(
+        log_buffer.clone(),
+        cache,
+        i_to_app.clone(),
+        parse_stats.clone(),
+        applications.clone(),
+        templates,
+        sys.clone(),
+    ));
+
     let shared_state = SharedState {
         log_buffer,
-        cache,
         i_to_app,
-        last_buffer_update: Arc::new(Mutex::new(SystemTime::now())),
+        parse_stats,
+        applications,
         sys,
         server_start_time: Arc::new(SystemTime::now()),
         os: Arc::new(os),
@@ -142,6 +194,75 @@ pub async fn run() {
         .unwrap();
 }
 
+// Runs the parser of a configured application against the lines of a file and prints the outcome of each line.
+// Returns false if the application or file can't be read, or if any of the lines failed to parse.
+pub fn test_parser(app_name: &str, file_path: &str) -> bool {
+    let applications = match log_reader::application::get_templates()
+        .and_then(|templates| log_reader::application::get_applications(&templates))
+    {
+        Ok(applications) => applications,
+        Err(err) => {
+            eprintln!("{:#}", err);
+            return false;
+        }
+    };
+
+    let app = match applications.into_iter().find(|app| app.name == app_name) {
+        Some(app) => app,
+        None => {
+            eprintln!("Unknown application: {}", app_name);
+            return false;
+        }
+    };
+
+    let bytes = match std::fs::read(file_path) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("Failed to read {}! {}", file_path, err);
+            return false;
+        }
+    };
+
+    let input = match app.encoding.decode(&bytes, app.lossy) {
+        Ok(input) => input,
+        Err(err) => {
+            eprintln!("{} in {}", err, file_path);
+            return false;
+        }
+    };
+
+    let result = log_reader::parser_test::test_parser(&input, &app);
+
+    for line in &result.lines {
+        if let Some(entry) = &line.entry {
+            println!("{}: OK", line.line_number);
+            println!(
+                "    timestamp: {}",
+                entry.timestamp.format(&Rfc3339).unwrap_or_default()
+            );
+            println!("    level: {}", entry.level);
+            println!("    module: {}", entry.module);
+            println!("    message: {}", entry.message);
+
+            for (key, value) in &entry.fields {
+                println!("    {}: {}", key, value);
+            }
+        } else if line.continuation {
+            println!("{}: CONTINUATION {}", line.line_number, line.line);
+        } else if let Some(error) = &line.error {
+            println!("{}: FAILED {}", line.line_number, error);
+        }
+
+        for warning in &line.warnings {
+            println!("    warning: {}", warning);
+        }
+    }
+
+    println!("{} lines parsed, {} failed", result.parsed, result.failed);
+
+    result.failed == 0
+}
+
 pub fn convert_app_to_i(app: &str, i_to_app: &MutexGuard<HashMap<usize, String>>) -> Option<usize> {
     // Application paths are stored in a hashmap that maps an index to the path. Before filtering, we need to convert back to index representation.
     i_to_app
@@ -153,18 +274,17 @@ pub fn convert_app_to_i(app: &str, i_to_app: &MutexGuard<HashMap<usize, String>>
 
 // This iterator yields the most recent log entry across all the buffers
 struct LogBufferIterator<'a> {
-    buffers: Vec<(&'a AllocRingBuffer<LogEntry>, isize)>,
+    // Number of entries left in each buffer, the next one is the last of them
+    buffers: Vec<(&'a LogBuffer, usize)>,
 }
 
 impl<'a> LogBufferIterator<'a> {
-    fn new(
-        buffer_map: &'a HashMap<usize, AllocRingBuffer<LogEntry>>,
-        app_filter: Option<usize>,
-    ) -> Self {
+    fn new(buffer_map: &'a HashMap<usize, LogBuffer>, app_filter: Option<usize>) -> Self {
         let buffers = buffer_map
             .iter()
             .filter(|entry| app_filter.is_none() || app_filter.unwrap() == *entry.0)
-            .map(|entry| (entry.1, -1))
+            .filter(|entry| !entry.1.is_empty())
+            .map(|entry| (entry.1, entry.1.len()))
             .collect();
 
         LogBufferIterator { buffers }
@@ -172,36 +292,28 @@ impl<'a> LogBufferIterator<'a> {
 }
 
 impl<'a> Iterator for LogBufferIterator<'a> {
-    type Item = &'a LogEntry;
+    type Item = LogEntryRef<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut latest_time: Option<&OffsetDateTime> = None;
-        let mut latest_entry: Option<&LogEntry> = None;
+        let mut latest_time: Option<i64> = None;
         let mut index: Option<usize> = None;
 
         for (i, buffer) in self.buffers.iter().enumerate() {
-            if let Some(entry) = buffer.0.get_signed(buffer.1) {
-                // -1 is the most recent entry, -2 is the second most recent, etc.
-                if let Some(current_latest) = latest_time {
-                    if entry.timestamp > *current_latest {
-                        latest_time = Some(&entry.timestamp);
-                        latest_entry = Some(entry);
-                        index = Some(i);
-                    }
-                } else {
-                    latest_time = Some(&entry.timestamp);
-                    latest_entry = Some(entry);
+            if let Some(timestamp) = buffer.0.timestamp_nanos(buffer.1 - 1) {
+                if latest_time.is_none_or(|latest_time| timestamp > latest_time) {
+                    latest_time = Some(timestamp);
                     index = Some(i);
                 }
             }
         }
 
-        if let Some(i) = index {
-            self.buffers[i].1 -= 1;
+        let i = index?;
+        let (buffer, remaining) = &mut self.buffers[i];
+        *remaining -= 1;
+        let latest_entry = buffer.get(*remaining);
 
-            if self.buffers[i].1 < -(self.buffers[i].0.len() as isize) {
-                self.buffers.remove(i);
-            }
+        if *remaining == 0 {
+            self.buffers.remove(i);
         }
 
         latest_entry
@@ -0,0 +1,393 @@
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+
+use config::{Value, ValueKind};
+use log::{debug, error, info, trace, warn};
+use sysinfo::System;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::{Mutex, RwLock};
+
+use crate::config::parse_byte_size;
+use crate::log_buffer::{enforce_max_memory, LogBuffer};
+use crate::log_reader::stats::ParseStats;
+use crate::log_reader::{
+    create_buffer, get_app_index, handle_parse_error, LineSource, ParseErrorPolicy,
+};
+use crate::SETTINGS;
+
+pub mod syslog;
+
+// Larger messages are truncated by the socket, TCP frames are limited to the same size
+const MAX_DATAGRAM_SIZE: usize = 65_536;
+
+// Including the space after the length of an octet counted frame
+const MAX_FRAME_LENGTH_DIGITS: u64 = 8;
+
+// Socket errors often persist, so receiving is retried with a growing delay instead of in a tight loop
+const MIN_RETRY_DELAY: Duration = Duration::from_millis(100);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+// Unparsable messages continue the entries of earlier messages to the same listener
+const LISTENER_SOURCE: u64 = u64::MAX;
+
+// What the tasks of a listener share
+struct Listener {
+    app_i: usize,
+    buffer: Arc<RwLock<HashMap<usize, LogBuffer>>>,
+    parse_stats: Arc<Mutex<HashMap<usize, BTreeMap<String, ParseStats>>>>,
+    on_parse_error: ParseErrorPolicy,
+}
+
+// Opens the syslog endpoints configured under [[listener]], each one gets its own application buffer
+pub async fn start_listeners(
+    buffer: Arc<RwLock<HashMap<usize, LogBuffer>>>,
+    i_to_app: Arc<Mutex<HashMap<usize, String>>>,
+    parse_stats: Arc<Mutex<HashMap<usize, BTreeMap<String, ParseStats>>>>,
+    sysinfo: Arc<Mutex<System>>,
+) {
+    let listeners = match SETTINGS.get_array("listener") {
+        Ok(listeners) => listeners,
+        Err(_) => return,
+    };
+
+    for listener in listeners {
+        let listener_table = listener
+            .into_table()
+            .expect("Config file is formatted incorrectly!");
+
+        let address = listener_table
+            .get("address")
+            .expect("A listener is missing the required address in the config!")
+            .clone()
+            .into_string()
+            .expect("Address is not a string!");
+
+        let protocol = listener_table
+            .get("protocol")
+            .map(|protocol| {
+                protocol
+                    .clone()
+                    .into_string()
+                    .expect("Protocol is not a string!")
+            })
+            .unwrap_or_else(|| "udp".to_string());
+
+        let name = listener_table
+            .get("name")
+            .map(|name| name.clone().into_string().expect("Name is not a string!"))
+            .unwrap_or_else(|| format!("syslog {}", address));
+
+        let on_parse_error = listener_table
+            .get("on_parse_error")
+            .map(|policy| {
+                let policy = policy
+                    .clone()
+                    .into_string()
+                    .expect("on_parse_error is not a string!");
+
+                ParseErrorPolicy::from_label(&policy).unwrap_or_else(|err| panic!("{}", err))
+            })
+            .unwrap_or(ParseErrorPolicy::Drop);
+
+        let buffer_bytes = listener_table.get("buffer_bytes").map(|buffer_bytes| {
+            let buffer_bytes = buffer_bytes
+                .clone()
+                .into_string()
+                .expect("buffer_bytes is not a string!");
+
+            parse_byte_size(&buffer_bytes)
+                .unwrap_or_else(|| panic!("Invalid buffer_bytes: {}", buffer_bytes))
+        });
+
+        let buffer_size = listener_table
+            .get("buffer_size")
+            .unwrap_or(&Value::new(
+                None,
+                ValueKind::U64(if buffer_bytes.is_some() {
+                    u64::MAX
+                } else {
+                    1_000_000
+                }),
+            ))
+            .clone()
+            .into_uint()
+            .expect("buffer_size is not parsable to an unsigned integer!");
+
+        let app_i = get_app_index(&mut *i_to_app.lock().await, &name);
+        if let Entry::Vacant(entry) = buffer.write().await.entry(app_i) {
+            entry.insert(
+                create_buffer(
+                    buffer_size,
+                    buffer_bytes,
+                    None,
+                    &name,
+                    app_i,
+                    &mut *sysinfo.lock().await,
+                )
+                .unwrap_or_else(|err| panic!("{}", err)),
+            );
+        }
+
+        let listener = Arc::new(Listener {
+            app_i,
+            buffer: buffer.clone(),
+            parse_stats: parse_stats.clone(),
+            on_parse_error,
+        });
+
+        let result = match protocol.as_str() {
+            "udp" => listen_udp(&address, listener).await,
+            "tcp" => listen_tcp(&address, listener).await,
+            "unix" => listen_unix(&address, listener).await,
+            unknown_protocol => panic!("Unknown listener protocol: {}", unknown_protocol),
+        };
+
+        match result {
+            Ok(_) => info!("Listening for syslog messages on {} {}", protocol, address),
+            Err(err) => error!(
+                "Failed to open syslog listener on {} {}! {}",
+                protocol, address, err
+            ),
+        }
+    }
+}
+
+async fn listen_udp(address: &str, listener: Arc<Listener>) -> std::io::Result<()> {
+    let socket = UdpSocket::bind(address).await?;
+
+    tokio::spawn(async move {
+        let mut datagram = vec![0; MAX_DATAGRAM_SIZE];
+        let mut retry_delay = Duration::ZERO;
+
+        loop {
+            match socket.recv(&mut datagram).await {
+                Ok(len) => {
+                    retry_delay = Duration::ZERO;
+                    push_message(&datagram[..len], &listener).await;
+                }
+                Err(err) => {
+                    error!("Failed to receive syslog message! {}", err);
+                    back_off(&mut retry_delay).await;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+async fn listen_tcp(address: &str, listener: Arc<Listener>) -> std::io::Result<()> {
+    let tcp_listener = TcpListener::bind(address).await?;
+
+    tokio::spawn(async move {
+        let mut retry_delay = Duration::ZERO;
+
+        loop {
+            match tcp_listener.accept().await {
+                Ok((stream, peer)) => {
+                    retry_delay = Duration::ZERO;
+                    debug!("Syslog connection from {}", peer);
+                    tokio::spawn(handle_tcp_connection(stream, listener.clone()));
+                }
+                Err(err) => {
+                    // E.g. when running out of file descriptors, which takes other connections to close
+                    error!("Failed to accept syslog connection! {}", err);
+                    back_off(&mut retry_delay).await;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+// Supports both octet counting ("12 <34>1 ...") and newline delimited framing (RFC 6587)
+async fn handle_tcp_connection(stream: TcpStream, listener: Arc<Listener>) {
+    let mut reader = BufReader::new(stream);
+    let mut frame = Vec::new();
+
+    loop {
+        frame.clear();
+
+        let is_octet_counted = match reader.fill_buf().await {
+            Ok([]) => break,
+            Ok(buffered) => buffered[0].is_ascii_digit(),
+            Err(err) => {
+                warn!("Syslog connection closed! {}", err);
+                break;
+            }
+        };
+
+        let result = if is_octet_counted {
+            read_octet_counted_frame(&mut reader, &mut frame).await
+        } else {
+            read_newline_frame(&mut reader, &mut frame).await
+        };
+
+        if let Err(err) = result {
+            warn!("Syslog connection closed! {}", err);
+            break;
+        }
+
+        push_message(&frame, &listener).await;
+    }
+}
+
+async fn read_octet_counted_frame(
+    reader: &mut BufReader<TcpStream>,
+    frame: &mut Vec<u8>,
+) -> std::io::Result<()> {
+    // Longer lengths are invalid anyway
+    let mut length = Vec::new();
+    (&mut *reader)
+        .take(MAX_FRAME_LENGTH_DIGITS)
+        .read_until(b' ', &mut length)
+        .await?;
+
+    let length = std::str::from_utf8(&length)
+        .ok()
+        .and_then(|length| length.trim_end().parse::<usize>().ok())
+        .filter(|length| *length <= MAX_DATAGRAM_SIZE)
+        .ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "Invalid frame length")
+        })?;
+
+    frame.resize(length, 0);
+    reader.read_exact(frame).await?;
+
+    Ok(())
+}
+
+// Newline delimited frames are limited to the same size as octet counted ones, as there is no telling where
+// the next frame starts once a frame is too large, the connection is closed
+async fn read_newline_frame(
+    reader: &mut BufReader<TcpStream>,
+    frame: &mut Vec<u8>,
+) -> std::io::Result<()> {
+    (&mut *reader)
+        .take(MAX_DATAGRAM_SIZE as u64 + 1)
+        .read_until(b'\n', frame)
+        .await?;
+
+    if frame.len() > MAX_DATAGRAM_SIZE && !frame.ends_with(b"\n") {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Frame is too large",
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn listen_unix(path: &str, listener: Arc<Listener>) -> std::io::Result<()> {
+    use tokio::net::UnixDatagram;
+
+    let socket = match UnixDatagram::bind(path) {
+        Err(err) if err.kind() == std::io::ErrorKind::AddrInUse => {
+            // A socket file left behind by a previous run can be replaced, one that is still in use can't
+            if UnixDatagram::unbound()?.connect(path).is_ok() {
+                return Err(err);
+            }
+
+            debug!("Removing stale socket file {}", path);
+            std::fs::remove_file(path)?;
+            UnixDatagram::bind(path)?
+        }
+        result => result?,
+    };
+
+    tokio::spawn(async move {
+        let mut datagram = vec![0; MAX_DATAGRAM_SIZE];
+        let mut retry_delay = Duration::ZERO;
+
+        loop {
+            match socket.recv(&mut datagram).await {
+                Ok(len) => {
+                    retry_delay = Duration::ZERO;
+                    push_message(&datagram[..len], &listener).await;
+                }
+                Err(err) => {
+                    error!("Failed to receive syslog message! {}", err);
+                    back_off(&mut retry_delay).await;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn listen_unix(_path: &str, _listener: Arc<Listener>) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "Unix sockets are not supported on this platform",
+    ))
+}
+
+// Doubles the delay after every consecutive error
+async fn back_off(retry_delay: &mut Duration) {
+    *retry_delay = (*retry_delay * 2).clamp(MIN_RETRY_DELAY, MAX_RETRY_DELAY);
+    tokio::time::sleep(*retry_delay).await;
+}
+
+// Messages that can't be parsed are handled by the listener's on_parse_error policy
+async fn push_message(message: &[u8], listener: &Listener) {
+    let message = String::from_utf8_lossy(message);
+
+    if message.trim().is_empty() {
+        return;
+    }
+
+    let mut stats = ParseStats::default();
+
+    {
+        let mut log_buffer_map = listener.buffer.write().await;
+        let log_buffer = match log_buffer_map.get_mut(&listener.app_i) {
+            Some(log_buffer) => log_buffer,
+            None => return,
+        };
+
+        match syslog::parse_message(&message, listener.app_i) {
+            Ok(entry) => {
+                trace!("{:?}", entry);
+                log_buffer.push_from(LISTENER_SOURCE, entry);
+            }
+            Err(err) => {
+                let result = handle_parse_error(
+                    err.into(),
+                    message.trim_end_matches(['\n', '\r', '\0']),
+                    &listener.on_parse_error,
+                    MAX_DATAGRAM_SIZE,
+                    &LineSource::new(LISTENER_SOURCE),
+                    log_buffer,
+                    &mut stats,
+                );
+
+                if let Err(err) = result {
+                    error!("{}", err);
+                }
+            }
+        }
+
+        log_buffer.commit(1); // Syslog messages carry the sender's timestamp
+        enforce_max_memory(&mut log_buffer_map);
+    }
+
+    // Received messages don't belong to a file, so their parse errors are grouped under "syslog"
+    if !stats.is_empty() {
+        listener
+            .parse_stats
+            .lock()
+            .await
+            .entry(listener.app_i)
+            .or_default()
+            .entry("syslog".to_string())
+            .or_default()
+            .merge(stats);
+    }
+}
@@ -0,0 +1,388 @@
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+use time::format_description::well_known::Rfc3339;
+use time::{Date, Duration, Month, OffsetDateTime, Time};
+
+use crate::LogEntry;
+
+#[derive(Debug, Error)]
+pub enum SyslogParseError {
+    #[error("Missing or invalid priority in message: {0}")]
+    MissingPriority(String),
+    #[error("Truncated RFC 5424 header in message: {0}")]
+    TruncatedHeader(String),
+    #[error("Invalid timestamp in message: {0}")]
+    InvalidTimestamp(String),
+}
+
+// Parses a single RFC 5424 or RFC 3164 syslog message
+pub fn parse_message(message: &str, app_i: usize) -> Result<LogEntry, SyslogParseError> {
+    let message = message.trim_end_matches(['\n', '\r', '\0']);
+
+    let (priority, rest) = message
+        .strip_prefix('<')
+        .and_then(|rest| rest.split_once('>'))
+        .and_then(|(priority, rest)| Some((priority.parse::<u8>().ok()?, rest)))
+        .filter(|(priority, _)| *priority <= 191)
+        .ok_or_else(|| SyslogParseError::MissingPriority(message.to_string()))?;
+
+    let level = severity_to_level(priority & 0x07);
+
+    if let Some(rest) = rest.strip_prefix("1 ") {
+        parse_rfc5424(message, rest, level, app_i)
+    } else {
+        Ok(parse_rfc3164(rest, level, app_i))
+    }
+}
+
+// <PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA MSG
+fn parse_rfc5424(
+    message: &str,
+    rest: &str,
+    level: log::Level,
+    app_i: usize,
+) -> Result<LogEntry, SyslogParseError> {
+    let mut header = rest.splitn(6, ' ');
+    let mut next_header_field = || {
+        header
+            .next()
+            .ok_or_else(|| SyslogParseError::TruncatedHeader(message.to_string()))
+    };
+
+    let timestamp = next_header_field()?;
+    let hostname = next_header_field()?;
+    let app_name = next_header_field()?;
+    let procid = next_header_field()?;
+    let msgid = next_header_field()?;
+    let rest = header.next().unwrap_or("");
+
+    let timestamp = match timestamp {
+        "-" => OffsetDateTime::now_utc(),
+        timestamp => OffsetDateTime::parse(timestamp, &Rfc3339)
+            .map_err(|_| SyslogParseError::InvalidTimestamp(message.to_string()))?,
+    };
+
+    let (structured_data, msg) = split_structured_data(rest);
+
+    let mut fields = BTreeMap::new();
+    for (key, value) in [
+        ("hostname", hostname),
+        ("procid", procid),
+        ("msgid", msgid),
+        ("structured_data", structured_data),
+    ] {
+        if value != "-" && !value.is_empty() {
+            fields.insert(key.to_string(), value.to_string());
+        }
+    }
+
+    Ok(LogEntry {
+        timestamp,
+        level,
+        module: if app_name == "-" { "N/A" } else { app_name }.to_string(),
+        message: msg.trim_start_matches('\u{feff}').to_string(),
+        fields,
+        application: app_i,
+        parse_failed: false,
+    })
+}
+
+// Structured data is either "-" or a list of [id key="value" ...] elements, where values may contain escaped brackets
+fn split_structured_data(rest: &str) -> (&str, &str) {
+    if !rest.starts_with('[') {
+        return rest.split_once(' ').unwrap_or((rest, ""));
+    }
+
+    let mut in_value = false;
+    let mut escaped = false;
+
+    for (i, c) in rest.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+
+        match c {
+            '\\' if in_value => escaped = true,
+            '"' => in_value = !in_value,
+            ']' if !in_value && !rest[i + 1..].starts_with('[') => {
+                let (structured_data, msg) = rest.split_at(i + 1);
+                return (structured_data, msg.strip_prefix(' ').unwrap_or(msg));
+            }
+            _ => {}
+        }
+    }
+
+    (rest, "")
+}
+
+// <PRI>Mmm dd hh:mm:ss HOSTNAME TAG[PID]: MSG, where the hostname is often left out by local senders
+fn parse_rfc3164(rest: &str, level: log::Level, app_i: usize) -> LogEntry {
+    let (timestamp, rest) = match rest.get(..15).and_then(parse_rfc3164_timestamp) {
+        Some(timestamp) => (timestamp, rest[15..].trim_start()),
+        None => (OffsetDateTime::now_utc(), rest),
+    };
+
+    let mut fields = BTreeMap::new();
+    let after_timestamp = rest;
+    let mut rest = rest;
+
+    if let Some((hostname, after_hostname)) = rest.split_once(' ') {
+        if !hostname.ends_with(':') && !hostname.contains('[') {
+            fields.insert("hostname".to_string(), hostname.to_string());
+            rest = after_hostname;
+        }
+    }
+
+    let tag_end = rest
+        .find(|c: char| c == ':' || c == '[' || c.is_whitespace())
+        .unwrap_or(rest.len());
+    let (tag, mut msg) = rest.split_at(tag_end);
+
+    if let Some(after_bracket) = msg.strip_prefix('[') {
+        if let Some((procid, after_procid)) = after_bracket.split_once(']') {
+            fields.insert("procid".to_string(), procid.to_string());
+            msg = after_procid;
+        }
+    }
+
+    let (module, msg) = match msg.strip_prefix(':') {
+        Some(msg) if !tag.is_empty() => (tag, msg.strip_prefix(' ').unwrap_or(msg)),
+        // Without a colon there is no tag and no hostname either, everything is the message
+        _ => {
+            fields.clear();
+            ("N/A", after_timestamp)
+        }
+    };
+
+    LogEntry {
+        timestamp,
+        level,
+        module: module.to_string(),
+        message: msg.to_string(),
+        fields,
+        application: app_i,
+        parse_failed: false,
+    }
+}
+
+// The timestamp has no year or offset, so the current year and UTC are assumed
+fn parse_rfc3164_timestamp(timestamp: &str) -> Option<OffsetDateTime> {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let month = MONTHS
+        .iter()
+        .position(|month| Some(*month) == timestamp.get(..3))
+        .and_then(|i| Month::try_from(i as u8 + 1).ok())?;
+    let day = timestamp.get(4..6)?.trim_start().parse().ok()?;
+    let time = Time::from_hms(
+        timestamp.get(7..9)?.parse().ok()?,
+        timestamp.get(10..12)?.parse().ok()?,
+        timestamp.get(13..15)?.parse().ok()?,
+    )
+    .ok()?;
+
+    let now = OffsetDateTime::now_utc();
+    let parsed = Date::from_calendar_date(now.year(), month, day)
+        .ok()?
+        .with_time(time)
+        .assume_utc();
+
+    // A message from December received in January belongs to the previous year
+    if parsed > now + Duration::days(1) {
+        Date::from_calendar_date(now.year() - 1, month, day)
+            .ok()
+            .map(|date| date.with_time(time).assume_utc())
+    } else {
+        Some(parsed)
+    }
+}
+
+pub fn severity_to_level(severity: u8) -> log::Level {
+    match severity {
+        0..=3 => log::Level::Error, // Emergency, alert, critical and error
+        4 => log::Level::Warn,
+        5 | 6 => log::Level::Info, // Notice and informational
+        _ => log::Level::Debug,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rfc5424() {
+        let entry = parse_message(
+            "<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 \
+             [exampleSDID@32473 iut=\"3\" eventSource=\"Application\"] An application event\n",
+            1,
+        )
+        .unwrap();
+
+        assert_eq!(
+            entry.timestamp,
+            OffsetDateTime::parse("2003-10-11T22:14:15.003Z", &Rfc3339).unwrap()
+        );
+        assert_eq!(entry.level, log::Level::Info);
+        assert_eq!(entry.module, "evntslog");
+        assert_eq!(entry.message, "An application event");
+        assert_eq!(entry.application, 1);
+        assert_eq!(entry.fields["hostname"], "mymachine.example.com");
+        assert_eq!(entry.fields["msgid"], "ID47");
+        assert_eq!(
+            entry.fields["structured_data"],
+            "[exampleSDID@32473 iut=\"3\" eventSource=\"Application\"]"
+        );
+        assert!(!entry.fields.contains_key("procid"));
+    }
+
+    #[test]
+    fn rfc5424_structured_data() {
+        let entry = parse_message(
+            "<11>1 2003-10-11T22:14:15Z host app 42 - [a x=\"b\\]c\"][d y=\"e f\"] message [not data]",
+            0,
+        )
+        .unwrap();
+
+        assert_eq!(entry.level, log::Level::Error);
+        assert_eq!(entry.fields["procid"], "42");
+        assert_eq!(
+            entry.fields["structured_data"],
+            "[a x=\"b\\]c\"][d y=\"e f\"]"
+        );
+        assert_eq!(entry.message, "message [not data]");
+    }
+
+    #[test]
+    fn rfc5424_nil_values() {
+        let entry = parse_message("<15>1 - - - - - - \u{feff}message", 0).unwrap();
+
+        assert_eq!(entry.level, log::Level::Debug);
+        assert_eq!(entry.module, "N/A");
+        assert_eq!(entry.message, "message");
+        assert!(entry.fields.is_empty());
+
+        // Nothing after the header is an empty message
+        let entry = parse_message("<14>1 2003-10-11T22:14:15Z host app - -", 0).unwrap();
+        assert_eq!(entry.message, "");
+    }
+
+    #[test]
+    fn rfc3164() {
+        let entry =
+            parse_message("<34>Oct 11 22:14:15 mymachine su[123]: 'su root' failed", 0).unwrap();
+
+        assert_eq!(entry.level, log::Level::Error);
+        assert_eq!(entry.timestamp.month(), Month::October);
+        assert_eq!(entry.timestamp.day(), 11);
+        assert_eq!(entry.timestamp.time(), Time::from_hms(22, 14, 15).unwrap());
+        assert_eq!(entry.module, "su");
+        assert_eq!(entry.message, "'su root' failed");
+        assert_eq!(entry.fields["hostname"], "mymachine");
+        assert_eq!(entry.fields["procid"], "123");
+    }
+
+    #[test]
+    fn rfc3164_without_hostname() {
+        let entry = parse_message("<13>Feb  5 17:32:18 sshd: Accepted publickey", 0).unwrap();
+
+        assert_eq!(entry.level, log::Level::Info);
+        assert_eq!(entry.timestamp.day(), 5);
+        assert_eq!(entry.module, "sshd");
+        assert_eq!(entry.message, "Accepted publickey");
+        assert!(entry.fields.is_empty());
+    }
+
+    #[test]
+    fn rfc3164_without_tag() {
+        let entry = parse_message("<12>just a message", 0).unwrap();
+
+        assert_eq!(entry.level, log::Level::Warn);
+        assert_eq!(entry.module, "N/A");
+        assert_eq!(entry.message, "just a message");
+
+        let entry = parse_message("<12>Oct 11 22:14:15 host message [1]", 0).unwrap();
+        assert_eq!(entry.message, "host message [1]");
+        assert!(entry.fields.is_empty());
+
+        // A timestamp that can't be parsed is part of the message
+        let entry = parse_message("<12>Foo 11 22:14:15 message", 0).unwrap();
+        assert_eq!(entry.message, "Foo 11 22:14:15 message");
+
+        // Multibyte characters where the timestamp would end
+        let entry = parse_message("<12>ääääääääää", 0).unwrap();
+        assert_eq!(entry.message, "ääääääääää");
+    }
+
+    #[test]
+    fn missing_priority() {
+        for message in [
+            "",
+            "hello",
+            "<>1 - - - - - -",
+            "<abc>message",
+            "<13 message",
+            "<192>message",
+            "<-1>message",
+        ] {
+            assert!(
+                matches!(
+                    parse_message(message, 0),
+                    Err(SyslogParseError::MissingPriority(_))
+                ),
+                "{}",
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_header() {
+        for message in [
+            "<13>1 ",
+            "<13>1 2003-10-11T22:14:15Z host",
+            "<13>1 - host app 1",
+        ] {
+            assert!(
+                matches!(
+                    parse_message(message, 0),
+                    Err(SyslogParseError::TruncatedHeader(_))
+                ),
+                "{}",
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_timestamp() {
+        for message in [
+            "<13>1 yesterday host app - - - message",
+            "<13>1 2003-10-11 host app - - - message",
+            "<13>1 2003-13-11T22:14:15Z host app - - - message",
+        ] {
+            assert!(
+                matches!(
+                    parse_message(message, 0),
+                    Err(SyslogParseError::InvalidTimestamp(_))
+                ),
+                "{}",
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn severity_levels() {
+        assert_eq!(severity_to_level(0), log::Level::Error);
+        assert_eq!(severity_to_level(3), log::Level::Error);
+        assert_eq!(severity_to_level(4), log::Level::Warn);
+        assert_eq!(severity_to_level(5), log::Level::Info);
+        assert_eq!(severity_to_level(6), log::Level::Info);
+        assert_eq!(severity_to_level(7), log::Level::Debug);
+    }
+}
@@ -0,0 +1,926 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::trace;
+use once_cell::sync::Lazy;
+use time::{OffsetDateTime, UtcOffset};
+use tokio::sync::RwLock;
+
+use crate::config::parse_byte_size;
+use crate::log_buffer::store::SegmentWriter;
+use crate::{LogEntry, SETTINGS};
+
+pub mod store;
+
+// Messages are stored in chunks of this size, a chunk is freed once all of its entries have been evicted
+const CHUNK_SIZE: usize = 256 * 1024;
+
+// The interned module names are rebuilt from the remaining entries when there are more than this many
+const MAX_MODULES: usize = 65_536;
+
+const PARSE_FAILED: u8 = 0b1000_0000;
+
+// How often entries that fell out of their application's retention window are evicted
+const EXPIRE_INTERVAL: Duration = Duration::from_secs(60);
+
+// How many of the older entries are sorted together with new ones, new entries that are older than all of them
+// are inserted one by one instead
+const REORDER_WINDOW: usize = 10_000;
+
+// Limit for the memory taken up by the entries of all applications together
+pub static MAX_MEMORY: Lazy<Option<usize>> = Lazy::new(|| {
+    SETTINGS
+        .get_string("main.max_memory")
+        .ok()
+        .map(|max_memory| {
+            parse_byte_size(&max_memory)
+                .unwrap_or_else(|| panic!("Invalid max_memory: {}", max_memory))
+                as usize
+        })
+});
+
+struct Chunk {
+    data: String,
+    entries: u32,
+}
+
+// Where the fields and the message of an entry are stored, the fields come first
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    chunk: u32,
+    start: u32,
+    fields_len: u32,
+    message_len: u32,
+}
+
+// Log entries of a single application, oldest first. Instead of keeping a LogEntry with its own allocations
+// per entry, the timestamp, level and module are kept in fixed-width columns and the messages in a shared arena.
+pub struct LogBuffer {
+    app_i: usize,
+    // The oldest entries are evicted when there are more entries than this or they take up more than max_bytes
+    capacity: usize,
+    max_bytes: Option<usize>,
+    retention: Option<Duration>,
+    bytes: usize,
+    // Allocated by the chunks, including the space that isn't used (yet)
+    chunk_bytes: usize,
+    module_bytes: usize,
+    timestamps: VecDeque<i64>, // Unix timestamp in nanoseconds
+    offsets: VecDeque<i32>,    // UTC offset in seconds
+    levels: VecDeque<u8>,
+    modules: VecDeque<u32>,
+    slots: VecDeque<Slot>,
+    module_names: Vec<Box<str>>,
+    module_ids: HashMap<Box<str>, u32>,
+    chunks: VecDeque<Chunk>,
+    first_chunk: u32, // Id of the first chunk in chunks
+    // Number of entries evicted so far, entries are tracked by their index counted from the first one ever pushed
+    evicted: u64,
+    // Last entry of each source (file or ingest stream), continuation lines are appended to it
+    last_entries: HashMap<u64, u64>,
+    // New entries are also written to disk when main.store_path is set
+    store: Option<SegmentWriter>,
+}
+
+// An entry as it is kept in the columns, also used for the on-disk format
+pub struct RawEntry<'a> {
+    timestamp: i64,
+    offset: i32,
+    level: u8,
+    module: &'a str,
+    message: &'a str,
+    fields: &'a str,
+}
+
+// Borrowed view of an entry in a LogBuffer
+#[derive(Debug, Clone, Copy)]
+pub struct LogEntryRef<'a> {
+    timestamp: i64,
+    offset: i32,
+    pub level: log::Level,
+    pub module: &'a str,
+    pub message: &'a str,
+    pub application: usize,
+    pub parse_failed: bool,
+    fields: &'a str,
+}
+
+impl LogBuffer {
+    pub fn new(
+        capacity: usize,
+        max_bytes: Option<usize>,
+        retention: Option<Duration>,
+        app_i: usize,
+    ) -> Self {
+        LogBuffer {
+            app_i,
+            capacity: capacity.max(1),
+            max_bytes,
+            retention,
+            bytes: 0,
+            chunk_bytes: 0,
+            module_bytes: 0,
+            timestamps: VecDeque::new(),
+            offsets: VecDeque::new(),
+            levels: VecDeque::new(),
+            modules: VecDeque::new(),
+            slots: VecDeque::new(),
+            module_names: Vec::new(),
+            module_ids: HashMap::new(),
+            chunks: VecDeque::new(),
+            first_chunk: 0,
+            evicted: 0,
+            last_entries: HashMap::new(),
+            store: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.timestamps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timestamps.is_empty()
+    }
+
+    pub fn app_i(&self) -> usize {
+        self.app_i
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn max_bytes(&self) -> Option<usize> {
+        self.max_bytes
+    }
+
+    pub fn retention(&self) -> Option<Duration> {
+        self.retention
+    }
+
+    // Number of entries pushed so far, including those that were evicted since
+    pub fn pushed(&self) -> u64 {
+        self.evicted + self.len() as u64
+    }
+
+    // Memory taken up by a single entry, without its message, module and fields
+    pub const fn entry_size() -> usize {
+        size_of::<i64>() + size_of::<i32>() + size_of::<u8>() + size_of::<u32>() + size_of::<Slot>()
+    }
+
+    // Memory allocated for the entries, the chunks and columns also take up space that isn't used (yet)
+    pub fn memory_usage(&self) -> usize {
+        let column_bytes = self.timestamps.capacity() * size_of::<i64>()
+            + self.offsets.capacity() * size_of::<i32>()
+            + self.levels.capacity() * size_of::<u8>()
+            + self.modules.capacity() * size_of::<u32>()
+            + self.slots.capacity() * size_of::<Slot>();
+
+        column_bytes + self.chunk_bytes + self.module_bytes
+    }
+
+    // Memory the entries can take up before the oldest ones are evicted. When only the number of entries is limited,
+    // this is estimated from the average size of the current entries. The space that isn't used by the entries
+    // themselves is added on top, as buffer_bytes only limits the entries.
+    pub fn memory_limit(&self) -> usize {
+        let average_size = match self.len() {
+            0 => Self::entry_size(),
+            len => self.bytes.div_ceil(len),
+        };
+
+        let capacity_bytes = self.capacity.saturating_mul(average_size);
+        self.max_bytes
+            .map_or(capacity_bytes, |max_bytes| max_bytes.min(capacity_bytes))
+            .saturating_add(self.memory_usage().saturating_sub(self.bytes))
+    }
+
+    // Unix timestamp in nanoseconds, cheaper to compare than the entry's OffsetDateTime
+    pub fn timestamp_nanos(&self, index: usize) -> Option<i64> {
+        self.timestamps.get(index).copied()
+    }
+
+    // 0 is the oldest entry
+    pub fn get(&self, index: usize) -> Option<LogEntryRef<'_>> {
+        let slot = *self.slots.get(index)?;
+        let data = &self.chunk(slot.chunk).data;
+        let fields_start = slot.start as usize;
+        let message_start = fields_start + slot.fields_len as usize;
+
+        Some(LogEntryRef {
+            timestamp: self.timestamps[index],
+            offset: self.offsets[index],
+            level: to_level(self.levels[index]),
+            module: &self.module_names[self.modules[index] as usize],
+            message: &data[message_start..message_start + slot.message_len as usize],
+            application: self.app_i,
+            parse_failed: self.levels[index] & PARSE_FAILED != 0,
+            fields: &data[fields_start..message_start],
+        })
+    }
+
+    // The oldest entries are evicted when the buffer is full
+    pub fn push(&mut self, entry: LogEntry) {
+        let fields = encode_fields(&entry.fields);
+
+        self.push_raw(RawEntry {
+            timestamp: i64::try_from(entry.timestamp.unix_timestamp_nanos()).unwrap_or(
+                if entry.timestamp.year() < 1970 {
+                    i64::MIN
+                } else {
+                    i64::MAX
+                },
+            ),
+            offset: entry.timestamp.offset().whole_seconds(),
+            level: entry.level as u8 | if entry.parse_failed { PARSE_FAILED } else { 0 },
+            module: &entry.module,
+            message: &entry.message,
+            fields: &fields,
+        });
+    }
+
+    // Also remembers the entry as the last one of its source
+    pub fn push_from(&mut self, source: u64, entry: LogEntry) {
+        self.push(entry);
+        self.last_entries
+            .insert(source, self.evicted + self.len() as u64 - 1);
+
+        if let Some(store) = &mut self.store {
+            store.write_source(source);
+        }
+    }
+
+    // Index of the last entry of the source, if it hasn't been evicted yet
+    pub fn last_entry(&self, source: u64) -> Option<usize> {
+        self.last_entries
+            .get(&source)
+            .and_then(|index| index.checked_sub(self.evicted))
+            .map(|index| index as usize)
+    }
+
+    fn push_raw(&mut self, entry: RawEntry) {
+        if let Some(store) = &mut self.store {
+            store.write_entry(&entry);
+        }
+
+        if self.len() == self.capacity {
+            self.evict_front();
+        }
+
+        let slot = self.write_slot(&[entry.fields], &[entry.message]);
+        let module = self.intern_module(entry.module);
+
+        // The columns shouldn't grow past the capacity
+        if self.timestamps.len() == self.timestamps.capacity() {
+            let additional = self
+                .timestamps
+                .len()
+                .max(1024)
+                .min(self.capacity - self.timestamps.len());
+
+            self.timestamps.reserve_exact(additional);
+            self.offsets.reserve_exact(additional);
+            self.levels.reserve_exact(additional);
+            self.modules.reserve_exact(additional);
+            self.slots.reserve_exact(additional);
+        }
+
+        self.timestamps.push_back(entry.timestamp);
+        self.offsets.push_back(entry.offset);
+        self.levels.push_back(entry.level);
+        self.modules.push_back(module);
+        self.slots.push_back(slot);
+        self.bytes += Self::entry_size() + slot_size(slot);
+
+        self.evict_over_max_bytes();
+    }
+
+    // Returns false if the buffer is empty
+    pub fn evict_front(&mut self) -> bool {
+        let slot = match self.slots.pop_front() {
+            Some(slot) => slot,
+            None => return false,
+        };
+
+        self.timestamps.pop_front();
+        self.offsets.pop_front();
+        self.levels.pop_front();
+        self.modules.pop_front();
+        self.release_slot(slot);
+        self.bytes -= Self::entry_size() + slot_size(slot);
+        self.evicted += 1;
+
+        // The columns give back their memory once most of their entries are gone
+        let min_capacity = self.len().max(1024);
+        if self.timestamps.capacity() > 4 * min_capacity {
+            self.timestamps.shrink_to(2 * min_capacity);
+            self.offsets.shrink_to(2 * min_capacity);
+            self.levels.shrink_to(2 * min_capacity);
+            self.modules.shrink_to(2 * min_capacity);
+            self.slots.shrink_to(2 * min_capacity);
+        }
+
+        true
+    }
+
+    // The newest entry is always kept, even if it doesn't fit by itself
+    fn evict_over_max_bytes(&mut self) {
+        if let Some(max_bytes) = self.max_bytes {
+            while self.bytes > max_bytes && self.len() > 1 {
+                self.evict_front();
+            }
+        }
+    }
+
+    // Called after a batch of entries was pushed, new_entries is an upper bound of their number
+    pub fn commit(&mut self, new_entries: usize) {
+        self.commit_read_state(new_entries, "");
+    }
+
+    // Like commit, the read state tells how far the sources of the batch have been read. It is stored together with
+    // the entries and handed back by take_read_states after a restart.
+    pub fn commit_read_state(&mut self, new_entries: usize, read_state: &str) {
+        self.sort_tail(new_entries);
+        self.evict_expired();
+
+        let evicted = self.evicted;
+        self.last_entries.retain(|_, index| *index >= evicted);
+
+        let len = self.len();
+        if let Some(store) = &mut self.store {
+            store.commit(new_entries, read_state, len);
+        }
+    }
+
+    // Read states of the stored batches, oldest first. Empty unless the buffer was just restored from the store.
+    pub fn take_read_states(&mut self) -> Vec<String> {
+        self.store
+            .as_mut()
+            .map(SegmentWriter::take_read_states)
+            .unwrap_or_default()
+    }
+
+    // Evicts the entries that are older than the retention window. Unlike with the size limits,
+    // this can leave the buffer empty. The entries have to be sorted, see sort_tail.
+    fn evict_expired(&mut self) {
+        let retention = match self.retention {
+            Some(retention) => retention,
+            None => return,
+        };
+
+        let window_start = match retention_window_start(retention) {
+            Some(window_start) => window_start,
+            None => return,
+        };
+        let mut evicted = 0;
+
+        while self
+            .timestamps
+            .front()
+            .is_some_and(|timestamp| *timestamp < window_start)
+        {
+            self.evict_front();
+            evicted += 1;
+        }
+
+        if evicted > 0 {
+            trace!("Evicted {} entries older than {:?}", evicted, retention);
+        }
+    }
+
+    // Used for multiline entries, the message is moved to the end of the arena if it isn't there already
+    pub fn append_to_message(&mut self, i: usize, line: &str) {
+        if i >= self.len() {
+            return;
+        }
+
+        // Counted from the newest entry, which stays the same when older entries are evicted
+        let back_offset = self.len() - 1 - i;
+        if let Some(store) = &mut self.store {
+            store.write_append(back_offset, line);
+        }
+
+        let slot = self.slots[i];
+        let current_chunk = self.first_chunk.wrapping_add(self.chunks.len() as u32 - 1);
+        let chunk = self.chunk_mut(slot.chunk);
+        let slot_end = slot.start as usize + slot_size(slot);
+
+        // Appending in place as long as the chunk doesn't have to grow
+        if slot.chunk == current_chunk
+            && slot_end == chunk.data.len()
+            && chunk.data.len() + 1 + line.len() <= chunk.data.capacity()
+        {
+            chunk.data.push('\n');
+            chunk.data.push_str(line);
+            self.slots[i].message_len += 1 + line.len() as u32;
+        } else {
+            let entry = self.get(i).unwrap();
+            let fields = entry.fields.to_string();
+            let message = entry.message.to_string();
+
+            self.release_slot(slot);
+            self.slots[i] = self.write_slot(&[&fields], &[&message, "\n", line]);
+        }
+
+        self.bytes += 1 + line.len();
+        self.evict_over_max_bytes();
+    }
+
+    // Entries are pushed in the order they are read, which doesn't have to be the order of their timestamps,
+    // e.g. when several files of an application are written to at the same time. Restores the order after at most
+    // new_entries were pushed, only the part of the buffer that the new entries belong to is sorted again,
+    // reaching back at most REORDER_WINDOW entries.
+    fn sort_tail(&mut self, new_entries: usize) {
+        let len = self.len();
+        let tail_start = len - new_entries.min(len);
+
+        let mut previous = tail_start
+            .checked_sub(1)
+            .map(|index| self.timestamps[index]);
+        let mut tail_min = None;
+        let mut is_sorted = true;
+
+        for &timestamp in self.timestamps.range(tail_start..) {
+            if previous.is_some_and(|previous| timestamp < previous) {
+                is_sorted = false;
+            }
+
+            previous = Some(timestamp);
+            tail_min = Some(tail_min.map_or(timestamp, |tail_min: i64| tail_min.min(timestamp)));
+        }
+
+        let tail_min = match tail_min {
+            Some(tail_min) if !is_sorted => tail_min,
+            _ => return,
+        };
+
+        // The older entries are already sorted, find the first one that is newer than the oldest new entry
+        let window_start = tail_start.saturating_sub(REORDER_WINDOW);
+        let merge_start = self.partition_point(window_start..tail_start, tail_min);
+
+        trace!(
+            "Sorting the last {} entries by timestamp",
+            len - merge_start
+        );
+
+        // The sort is stable, so entries with the same timestamp keep their order
+        let mut order: Vec<usize> = (merge_start..len).collect();
+        order.sort_by_key(|i| self.timestamps[*i]);
+
+        let timestamps: Vec<i64> = order.iter().map(|i| self.timestamps[*i]).collect();
+        let offsets: Vec<i32> = order.iter().map(|i| self.offsets[*i]).collect();
+        let levels: Vec<u8> = order.iter().map(|i| self.levels[*i]).collect();
+        let modules: Vec<u32> = order.iter().map(|i| self.modules[*i]).collect();
+        let slots: Vec<Slot> = order.iter().map(|i| self.slots[*i]).collect();
+
+        for (sorted_i, i) in (merge_start..len).enumerate() {
+            self.timestamps[i] = timestamps[sorted_i];
+            self.offsets[i] = offsets[sorted_i];
+            self.levels[i] = levels[sorted_i];
+            self.modules[i] = modules[sorted_i];
+            self.slots[i] = slots[sorted_i];
+        }
+
+        // The last entries of the sources move along with the sorted entries
+        let mut sorted_positions = vec![0; len - merge_start];
+        for (sorted_i, i) in order.iter().enumerate() {
+            sorted_positions[i - merge_start] = merge_start + sorted_i;
+        }
+
+        let evicted = self.evicted;
+        for index in self.last_entries.values_mut() {
+            if let Some(i) = index
+                .checked_sub(evicted)
+                .filter(|i| *i as usize >= merge_start)
+            {
+                *index = evicted + sorted_positions[i as usize - merge_start] as u64;
+            }
+        }
+
+        // New entries that are older than the whole window are moved in front of it one at a time
+        let mut i = merge_start;
+        while i > 0 && i < len && self.timestamps[i] < self.timestamps[i - 1] {
+            let position = self.partition_point(0..i, self.timestamps[i]);
+            self.move_entry(i, position);
+            i += 1;
+        }
+    }
+
+    // Index of the first entry in the sorted range that is newer than the timestamp
+    fn partition_point(&self, range: Range<usize>, timestamp: i64) -> usize {
+        let (mut start, mut end) = (range.start, range.end);
+
+        while start < end {
+            let middle = (start + end) / 2;
+
+            if self.timestamps[middle] <= timestamp {
+                start = middle + 1;
+            } else {
+                end = middle;
+            }
+        }
+
+        start
+    }
+
+    // Moves the entry at from to the earlier index to, the entries in between move back by one
+    fn move_entry(&mut self, from: usize, to: usize) {
+        fn move_back<T>(column: &mut VecDeque<T>, from: usize, to: usize) {
+            if let Some(value) = column.remove(from) {
+                column.insert(to, value);
+            }
+        }
+
+        move_back(&mut self.timestamps, from, to);
+        move_back(&mut self.offsets, from, to);
+        move_back(&mut self.levels, from, to);
+        move_back(&mut self.modules, from, to);
+        move_back(&mut self.slots, from, to);
+
+        let (from, to) = (self.evicted + from as u64, self.evicted + to as u64);
+        for index in self.last_entries.values_mut() {
+            if *index == from {
+                *index = to;
+            } else if (to..from).contains(index) {
+                *index += 1;
+            }
+        }
+    }
+
+    fn chunk(&self, chunk_id: u32) -> &Chunk {
+        &self.chunks[chunk_id.wrapping_sub(self.first_chunk) as usize]
+    }
+
+    fn chunk_mut(&mut self, chunk_id: u32) -> &mut Chunk {
+        &mut self.chunks[chunk_id.wrapping_sub(self.first_chunk) as usize]
+    }
+
+    // Writes the parts one after another into the arena, starting a new chunk if the current one is full
+    fn write_slot(&mut self, fields: &[&str], message: &[&str]) -> Slot {
+        let fields_len: usize = fields.iter().map(|part| part.len()).sum();
+        let message_len: usize = message.iter().map(|part| part.len()).sum();
+
+        let has_room = self
+            .chunks
+            .back()
+            .is_some_and(|chunk| chunk.data.len() + fields_len + message_len <= CHUNK_SIZE);
+
+        if !has_room {
+            let data = String::with_capacity(CHUNK_SIZE.max(fields_len + message_len));
+            self.chunk_bytes += data.capacity();
+            self.chunks.push_back(Chunk { data, entries: 0 });
+        }
+
+        let chunk_id = self.first_chunk.wrapping_add(self.chunks.len() as u32 - 1);
+        let chunk = self.chunks.back_mut().unwrap();
+        let start = chunk.data.len() as u32;
+
+        for part in fields.iter().chain(message) {
+            chunk.data.push_str(part);
+        }
+        chunk.entries += 1;
+
+        Slot {
+            chunk: chunk_id,
+            start,
+            fields_len: fields_len as u32,
+            message_len: message_len as u32,
+        }
+    }
+
+    fn release_slot(&mut self, slot: Slot) {
+        let is_current =
+            slot.chunk.wrapping_sub(self.first_chunk) as usize == self.chunks.len() - 1;
+        let chunk = self.chunk_mut(slot.chunk);
+        chunk.entries -= 1;
+
+        if chunk.entries == 0 && !is_current {
+            let data = std::mem::take(&mut chunk.data);
+            self.chunk_bytes -= data.capacity();
+        } else if chunk.entries == 0 {
+            // The current chunk is written to again from the start, chunks of large messages are shrunk first
+            let capacity = chunk.data.capacity();
+            chunk.data.clear();
+            chunk.data.shrink_to(CHUNK_SIZE);
+            let shrunk_by = capacity - chunk.data.capacity();
+            self.chunk_bytes -= shrunk_by;
+        }
+
+        // Chunks are usually emptied in order, those in the middle are only freed until then
+        while self.chunks.len() > 1 && self.chunks.front().is_some_and(|chunk| chunk.entries == 0) {
+            if let Some(chunk) = self.chunks.pop_front() {
+                self.chunk_bytes -= chunk.data.capacity();
+            }
+            self.first_chunk = self.first_chunk.wrapping_add(1);
+        }
+    }
+
+    fn intern_module(&mut self, module: &str) -> u32 {
+        if let Some(module_id) = self.module_ids.get(module) {
+            return *module_id;
+        }
+
+        if self.module_names.len() >= MAX_MODULES {
+            self.compact_modules();
+        }
+
+        let module_id = self.module_names.len() as u32;
+        self.module_names.push(module.into());
+        self.module_ids.insert(module.into(), module_id);
+        self.bytes += module_size(module);
+        self.module_bytes += module_size(module);
+        module_id
+    }
+
+    // Drops the names of modules that no longer have any entries
+    fn compact_modules(&mut self) {
+        let mut module_names = Vec::new();
+        let mut module_ids: HashMap<Box<str>, u32> = HashMap::new();
+
+        for module in self.modules.iter_mut() {
+            let name = &self.module_names[*module as usize];
+
+            *module = *module_ids.entry(name.clone()).or_insert_with(|| {
+                module_names.push(name.clone());
+                module_names.len() as u32 - 1
+            });
+        }
+
+        let module_bytes =
+            |names: &[Box<str>]| names.iter().map(|name| module_size(name)).sum::<usize>();
+        self.bytes = self.bytes - self.module_bytes + module_bytes(&module_names);
+        self.module_bytes = module_bytes(&module_names);
+        self.module_names = module_names;
+        self.module_ids = module_ids;
+    }
+}
+
+// Start of the retention window as a Unix timestamp in nanoseconds. None if the window reaches back further than
+// the timestamps can, then nothing is old enough to be evicted.
+pub fn retention_window_start(retention: Duration) -> Option<i64> {
+    let window_start = OffsetDateTime::now_utc().checked_sub(retention.try_into().ok()?)?;
+    i64::try_from(window_start.unix_timestamp_nanos()).ok()
+}
+
+// Entries also fall out of the retention window while their application isn't logging anything
+pub async fn expire_entries(buffer: Arc<RwLock<HashMap<usize, LogBuffer>>>) {
+    let mut interval = tokio::time::interval(EXPIRE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        for log_buffer in buffer.write().await.values_mut() {
+            log_buffer.evict_expired();
+        }
+    }
+}
+
+// Evicts the oldest entries across all applications until they fit into main.max_memory together.
+// Like with buffer_bytes, the newest entry of each application is kept.
+pub fn enforce_max_memory(buffer_map: &mut HashMap<usize, LogBuffer>) {
+    let max_memory = match *MAX_MEMORY {
+        Some(max_memory) => max_memory,
+        None => return,
+    };
+
+    let mut memory_usage: usize = buffer_map.values().map(LogBuffer::memory_usage).sum();
+    let mut evicted = 0;
+
+    while memory_usage > max_memory {
+        let oldest_buffer = buffer_map
+            .values_mut()
+            .filter(|buffer| buffer.len() > 1)
+            .min_by_key(|buffer| buffer.timestamp_nanos(0));
+
+        match oldest_buffer {
+            Some(buffer) => {
+                let buffer_usage = buffer.memory_usage();
+                buffer.evict_front();
+                memory_usage -= buffer_usage - buffer.memory_usage();
+                evicted += 1;
+            }
+            None => break,
+        }
+    }
+
+    if evicted > 0 {
+        trace!("Evicted {} entries to stay within max_memory", evicted);
+    }
+}
+
+impl<'a> LogEntryRef<'a> {
+    // Only converted when needed, as this is done for every entry that is looked at
+    pub fn timestamp(&self) -> OffsetDateTime {
+        to_datetime(self.timestamp, self.offset)
+    }
+
+    // Fields are stored as <key length>:<key><value length>:<value>, one after another
+    pub fn fields(&self) -> impl Iterator<Item = (&'a str, &'a str)> {
+        let mut rest = self.fields;
+
+        std::iter::from_fn(move || {
+            let key = take_field_part(&mut rest)?;
+            let value = take_field_part(&mut rest)?;
+            Some((key, value))
+        })
+    }
+
+    pub fn field(&self, key: &str) -> Option<&'a str> {
+        self.fields()
+            .find(|(field_key, _)| *field_key == key)
+            .map(|(_, value)| value)
+    }
+
+    pub fn to_entry(self) -> LogEntry {
+        LogEntry {
+            timestamp: self.timestamp(),
+            level: self.level,
+            module: self.module.to_string(),
+            message: self.message.to_string(),
+            fields: self
+                .fields()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            application: self.application,
+            parse_failed: self.parse_failed,
+        }
+    }
+}
+
+fn slot_size(slot: Slot) -> usize {
+    (slot.fields_len + slot.message_len) as usize
+}
+
+// Module names are kept both in the list and as the keys of the lookup map
+fn module_size(module: &str) -> usize {
+    2 * (module.len() + size_of::<Box<str>>()) + size_of::<u32>()
+}
+
+fn encode_fields(fields: &BTreeMap<String, String>) -> String {
+    let mut encoded = String::new();
+
+    for (key, value) in fields {
+        for part in [key, value] {
+            encoded.push_str(&part.len().to_string());
+            encoded.push(':');
+            encoded.push_str(part);
+        }
+    }
+
+    encoded
+}
+
+fn take_field_part<'a>(rest: &mut &'a str) -> Option<&'a str> {
+    let (len, remaining) = rest.split_once(':')?;
+    let len: usize = len.parse().ok()?;
+    let part = remaining.get(..len)?;
+
+    *rest = &remaining[len..];
+    Some(part)
+}
+
+fn to_datetime(timestamp: i64, offset: i32) -> OffsetDateTime {
+    let timestamp = OffsetDateTime::from_unix_timestamp_nanos(timestamp as i128)
+        .unwrap_or(OffsetDateTime::UNIX_EPOCH);
+
+    match UtcOffset::from_whole_seconds(offset) {
+        Ok(offset) => timestamp.to_offset(offset),
+        Err(_) => timestamp,
+    }
+}
+
+fn to_level(level: u8) -> log::Level {
+    match level & !PARSE_FAILED {
+        1 => log::Level::Error,
+        2 => log::Level::Warn,
+        3 => log::Level::Info,
+        4 => log::Level::Debug,
+        _ => log::Level::Trace,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(timestamp: OffsetDateTime, message: &str) -> LogEntry {
+        LogEntry {
+            timestamp,
+            level: log::Level::Info,
+            module: "module".to_string(),
+            message: message.to_string(),
+            fields: BTreeMap::new(),
+            application: 0,
+            parse_failed: false,
+        }
+    }
+
+    fn timestamp(seconds: i64) -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(seconds).unwrap()
+    }
+
+    fn messages(log_buffer: &LogBuffer) -> Vec<&str> {
+        (0..log_buffer.len())
+            .map(|i| log_buffer.get(i).unwrap().message)
+            .collect()
+    }
+
+    #[test]
+    fn out_of_order_appends() {
+        let mut log_buffer = LogBuffer::new(100, None, None, 0);
+        for (seconds, message) in [(1, "1"), (2, "2"), (4, "4"), (5, "5")] {
+            log_buffer.push(entry(timestamp(seconds), message));
+        }
+        log_buffer.commit(4);
+
+        log_buffer.push_from(7, entry(timestamp(3), "3"));
+        log_buffer.push(entry(timestamp(6), "6"));
+        log_buffer.push(entry(timestamp(2), "2 again"));
+        log_buffer.commit(3);
+
+        assert_eq!(
+            messages(&log_buffer),
+            ["1", "2", "2 again", "3", "4", "5", "6"]
+        );
+        assert_eq!(log_buffer.last_entry(7), Some(3));
+    }
+
+    #[test]
+    fn out_of_order_appends_beyond_window() {
+        let mut log_buffer = LogBuffer::new(REORDER_WINDOW * 2, None, None, 0);
+        for seconds in 0..REORDER_WINDOW as i64 + 10 {
+            log_buffer.push(entry(timestamp(100 + seconds), "window"));
+        }
+        log_buffer.commit(REORDER_WINDOW + 10);
+
+        log_buffer.push_from(7, entry(timestamp(50), "older"));
+        log_buffer.push(entry(timestamp(105), "within"));
+        log_buffer.push_from(8, entry(timestamp(101), "straggler"));
+        log_buffer.commit(3);
+
+        assert!((1..log_buffer.len()).all(|i| {
+            log_buffer.timestamp_nanos(i - 1).unwrap() <= log_buffer.timestamp_nanos(i).unwrap()
+        }));
+        assert_eq!(log_buffer.get(0).unwrap().message, "older");
+        assert_eq!(log_buffer.get(3).unwrap().message, "straggler");
+        assert_eq!(log_buffer.get(8).unwrap().message, "within");
+        assert_eq!(log_buffer.last_entry(7), Some(0));
+        assert_eq!(log_buffer.last_entry(8), Some(3));
+    }
+
+    #[test]
+    fn memory_usage_after_eviction() {
+        let columns = 1024 * LogBuffer::entry_size();
+
+        // The chunk of the large message is still the one being written to when its entry is evicted
+        let mut log_buffer = LogBuffer::new(1, None, None, 0);
+        log_buffer.push(entry(timestamp(1), &"x".repeat(4 * CHUNK_SIZE)));
+        log_buffer.push(entry(timestamp(2), "small"));
+        log_buffer.commit(2);
+
+        assert_eq!(messages(&log_buffer), ["small"]);
+        assert!(log_buffer.memory_usage() <= CHUNK_SIZE + columns);
+
+        // Also when all of the entries are evicted
+        let mut log_buffer = LogBuffer::new(10, None, Some(Duration::from_secs(60)), 0);
+        log_buffer.push(entry(timestamp(1), &"x".repeat(4 * CHUNK_SIZE)));
+        log_buffer.commit(1);
+
+        assert!(log_buffer.is_empty());
+        assert!(log_buffer.memory_usage() <= CHUNK_SIZE + columns);
+
+        log_buffer.push(entry(OffsetDateTime::now_utc(), "new"));
+        assert_eq!(messages(&log_buffer), ["new"]);
+        assert!(log_buffer.memory_usage() <= CHUNK_SIZE + columns);
+    }
+
+    #[test]
+    fn retention() {
+        let mut log_buffer = LogBuffer::new(10, None, Some(Duration::from_secs(60)), 0);
+        log_buffer.push(entry(
+            OffsetDateTime::now_utc() - Duration::from_secs(120),
+            "expired",
+        ));
+        log_buffer.push(entry(OffsetDateTime::now_utc(), "kept"));
+        log_buffer.commit(2);
+
+        assert_eq!(log_buffer.len(), 1);
+        assert_eq!(log_buffer.get(0).unwrap().message, "kept");
+    }
+
+    #[test]
+    fn retention_beyond_timestamps() {
+        assert_eq!(retention_window_start(Duration::MAX), None);
+        assert_eq!(
+            retention_window_start(Duration::from_secs(400 * 365 * 24 * 60 * 60)),
+            None
+        );
+
+        let mut log_buffer = LogBuffer::new(10, None, Some(Duration::MAX), 0);
+        log_buffer.push(entry(OffsetDateTime::UNIX_EPOCH, "kept"));
+        log_buffer.commit(1);
+        assert_eq!(log_buffer.len(), 1);
+    }
+}
@@ -0,0 +1,816 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::{debug, error, trace, warn};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::log_buffer::{retention_window_start, LogBuffer, RawEntry};
+use crate::SETTINGS;
+
+// A new segment is started once the current one has grown past this size
+const SEGMENT_SIZE: u64 = 16 * 1024 * 1024;
+
+// Every segment starts with this, the number is bumped when the format changes
+const SEGMENT_HEADER: &[u8] = b"LPSEG003";
+
+const ENTRY_RECORD: u8 = 0;
+const APPEND_RECORD: u8 = 1;
+const COMMIT_RECORD: u8 = 2;
+const SOURCE_RECORD: u8 = 3;
+
+// Directory the parsed entries are kept in, so that they don't have to be parsed again after a restart
+pub static STORE_PATH: Lazy<Option<PathBuf>> = Lazy::new(|| {
+    SETTINGS
+        .get_string("main.store_path")
+        .ok()
+        .map(PathBuf::from)
+});
+
+// Written next to a segment once it is full, so that it doesn't have to be read to decide whether it is still needed
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct SegmentIndex {
+    entries: u64,
+    min_timestamp: i64,
+    max_timestamp: i64,
+}
+
+struct Segment {
+    sequence: u64,
+    size: u64,
+    index: SegmentIndex,
+    is_sealed: bool,
+}
+
+// Records as they are read back from a segment
+enum Record<'a> {
+    Entry(RawEntry<'a>),
+    Append(usize, &'a str), // Counted from the newest entry
+    Source(u64),            // The newest entry is the last one of this source
+    Commit(usize, &'a str), // Number of new entries and the read state of the batch
+}
+
+// Appends the entries of an application to its segments, <store_path>/<application>/<sequence>.seg.
+// Records are collected in memory and written out when the buffer is committed, a batch without its commit
+// record is discarded when the segments are read back.
+pub struct SegmentWriter {
+    dir: PathBuf,
+    file: File,
+    current: Segment,
+    sealed: VecDeque<Segment>, // Oldest first
+    pending: Vec<u8>,
+    retention: Option<Duration>,
+    // Read states of the replayed batches, oldest first, until they are taken by the reader
+    read_states: Vec<String>,
+}
+
+// Loads the stored entries of the application into its buffer and attaches a writer for the new ones.
+// Segments that are no longer needed to fill the buffer are deleted.
+pub fn open(log_buffer: &mut LogBuffer, app_name: &str) -> io::Result<()> {
+    let store_path = match &*STORE_PATH {
+        Some(store_path) => store_path,
+        None => return Ok(()),
+    };
+
+    let dir = store_path.join(encode_app_name(app_name));
+    log_buffer.store = Some(SegmentWriter::open(dir, log_buffer)?);
+
+    debug!(
+        "Loaded {} stored entries for application {}",
+        log_buffer.len(),
+        app_name
+    );
+    Ok(())
+}
+
+impl SegmentWriter {
+    fn open(dir: PathBuf, log_buffer: &mut LogBuffer) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+
+        let mut segments = list_segments(&dir)?;
+        let next_sequence = segments.back().map_or(0, |segment| segment.sequence + 1);
+
+        // The buffer can't hold more entries than its capacity, the other limits are applied by replaying
+        let first_needed = first_needed(&segments, log_buffer.capacity(), log_buffer.retention());
+
+        for segment in segments.drain(..first_needed) {
+            trace!("Deleting segment {} in {:?}", segment.sequence, dir);
+            delete_segment(&dir, segment.sequence);
+        }
+
+        let read_states = replay(&dir, &mut segments, log_buffer);
+
+        // Writing continues in the last segment unless it is already full
+        let (file, current) = match segments.pop_back() {
+            Some(segment) if !segment.is_sealed => {
+                let file = OpenOptions::new().append(true).open(segment_path(
+                    &dir,
+                    segment.sequence,
+                    "seg",
+                ))?;
+
+                // Cuts off a record that was only partially written when the server stopped
+                file.set_len(segment.size)?;
+                (file, segment)
+            }
+            last_segment => {
+                segments.extend(last_segment);
+                create_segment(&dir, next_sequence)?
+            }
+        };
+
+        let mut writer = SegmentWriter {
+            dir,
+            file,
+            current,
+            sealed: segments,
+            pending: Vec::new(),
+            retention: log_buffer.retention(),
+            read_states,
+        };
+        writer.delete_unneeded(log_buffer.len());
+        Ok(writer)
+    }
+
+    pub fn take_read_states(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.read_states)
+    }
+
+    pub fn write_entry(&mut self, entry: &RawEntry) {
+        self.pending.push(ENTRY_RECORD);
+        self.pending
+            .extend_from_slice(&entry.timestamp.to_le_bytes());
+        self.pending.extend_from_slice(&entry.offset.to_le_bytes());
+        self.pending.push(entry.level);
+
+        for part in [entry.module, entry.message, entry.fields] {
+            self.write_str(part);
+        }
+
+        self.current.index.add(entry.timestamp);
+    }
+
+    pub fn write_append(&mut self, back_offset: usize, line: &str) {
+        self.pending.push(APPEND_RECORD);
+        self.pending
+            .extend_from_slice(&(back_offset as u64).to_le_bytes());
+        self.write_str(line);
+    }
+
+    pub fn write_source(&mut self, source: u64) {
+        self.pending.push(SOURCE_RECORD);
+        self.pending.extend_from_slice(&source.to_le_bytes());
+    }
+
+    // Writes out the records of the batch, new_entries is passed on to sort_tail when the segment is read back.
+    // The read state is stored in the same write, so that it always matches the stored entries.
+    // buffered_entries is the number of entries the buffer holds after the batch.
+    pub fn commit(&mut self, new_entries: usize, read_state: &str, buffered_entries: usize) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        self.pending.push(COMMIT_RECORD);
+        self.pending
+            .extend_from_slice(&(new_entries as u64).to_le_bytes());
+        self.write_str(read_state);
+
+        match self.file.write_all(&self.pending) {
+            Ok(_) => self.current.size += self.pending.len() as u64,
+            Err(err) => {
+                error!("Failed to write to segment in {:?}! {}", self.dir, err);
+
+                // A partially written batch would hide the ones written after it
+                if let Err(err) = self.file.set_len(self.current.size) {
+                    error!("Failed to truncate segment in {:?}! {}", self.dir, err);
+                }
+            }
+        }
+
+        self.pending.clear();
+
+        if self.current.size >= SEGMENT_SIZE {
+            if let Err(err) = self.seal() {
+                error!("Failed to start a new segment in {:?}! {}", self.dir, err);
+            }
+        }
+
+        self.delete_unneeded(buffered_entries);
+    }
+
+    fn seal(&mut self) -> io::Result<()> {
+        let index_path = segment_path(&self.dir, self.current.sequence, "idx");
+        fs::write(index_path, serde_json::to_vec(&self.current.index)?)?;
+
+        let (file, segment) = create_segment(&self.dir, self.current.sequence + 1)?;
+        let mut sealed = std::mem::replace(&mut self.current, segment);
+        sealed.is_sealed = true;
+
+        trace!("Sealed segment {} in {:?}", sealed.sequence, self.dir);
+        self.file = file;
+        self.sealed.push_back(sealed);
+        Ok(())
+    }
+
+    // Sealed segments that are older than the newest ones, which together hold as many entries as the buffer.
+    // This follows the buffer's eviction, whichever limit it was that evicted the entries.
+    fn delete_unneeded(&mut self, buffered_entries: usize) {
+        let first_needed = first_needed(
+            &self.sealed,
+            buffered_entries.saturating_sub(self.current.index.entries as usize),
+            self.retention,
+        );
+
+        for segment in self.sealed.drain(..first_needed) {
+            trace!("Deleting segment {} in {:?}", segment.sequence, self.dir);
+            delete_segment(&self.dir, segment.sequence);
+        }
+    }
+
+    fn write_str(&mut self, part: &str) {
+        self.pending
+            .extend_from_slice(&(part.len() as u32).to_le_bytes());
+        self.pending.extend_from_slice(part.as_bytes());
+    }
+}
+
+// Pushes the stored entries the same way they were pushed originally, so that they end up in the same order.
+// Returns the read states of the batches.
+fn replay(dir: &Path, segments: &mut VecDeque<Segment>, log_buffer: &mut LogBuffer) -> Vec<String> {
+    let mut read_states = Vec::new();
+
+    for segment in segments.iter_mut() {
+        let path = segment_path(dir, segment.sequence, "seg");
+        let result = read_segment(&path, |record| match record {
+            Record::Entry(entry) => log_buffer.push_raw(entry),
+            Record::Append(back_offset, line) => {
+                if let Some(i) = log_buffer.len().checked_sub(back_offset + 1) {
+                    log_buffer.append_to_message(i, line);
+                }
+            }
+            Record::Source(source) => {
+                if let Some(last_entry) = log_buffer.pushed().checked_sub(1) {
+                    log_buffer.last_entries.insert(source, last_entry);
+                }
+            }
+            Record::Commit(new_entries, read_state) => {
+                log_buffer.sort_tail(new_entries);
+
+                if !read_state.is_empty() {
+                    read_states.push(read_state.to_string());
+                }
+            }
+        });
+
+        match result {
+            Ok((_, valid_len)) => segment.size = valid_len,
+            Err(err) => error!("Failed to read segment {:?}! {}", path, err),
+        }
+    }
+
+    log_buffer.evict_expired();
+
+    let evicted = log_buffer.evicted;
+    log_buffer.last_entries.retain(|_, index| *index >= evicted);
+    read_states
+}
+
+impl SegmentIndex {
+    fn new() -> Self {
+        SegmentIndex {
+            entries: 0,
+            min_timestamp: i64::MAX,
+            max_timestamp: i64::MIN,
+        }
+    }
+
+    fn add(&mut self, timestamp: i64) {
+        self.entries += 1;
+        self.min_timestamp = self.min_timestamp.min(timestamp);
+        self.max_timestamp = self.max_timestamp.max(timestamp);
+    }
+}
+
+// Going from the newest segment, the ones after which there are already enough entries, or whose entries
+// are all older than the retention window, are not needed. Returns the index of the oldest segment that is.
+fn first_needed(
+    segments: &VecDeque<Segment>,
+    needed_entries: usize,
+    retention: Option<Duration>,
+) -> usize {
+    let window_start = retention.and_then(retention_window_start);
+    let mut entries: usize = 0;
+
+    for (i, segment) in segments.iter().enumerate().rev() {
+        if entries >= needed_entries {
+            return i + 1;
+        }
+
+        if window_start.is_some_and(|window_start| segment.index.max_timestamp < window_start) {
+            return i + 1;
+        }
+
+        entries += segment.index.entries as usize;
+    }
+
+    0
+}
+
+// Sorted by sequence number. The index of segments that haven't been sealed is built by reading them.
+fn list_segments(dir: &Path) -> io::Result<VecDeque<Segment>> {
+    let mut sequences = Vec::new();
+
+    for dir_entry in fs::read_dir(dir)? {
+        let path = dir_entry?.path();
+
+        if path.extension().is_some_and(|extension| extension == "seg") {
+            match path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok())
+            {
+                Some(sequence) => sequences.push(sequence),
+                None => warn!("Ignoring unknown file {:?} in the store", path),
+            }
+        }
+    }
+
+    sequences.sort_unstable();
+    let mut segments = VecDeque::new();
+
+    for sequence in sequences {
+        let path = segment_path(dir, sequence, "seg");
+        let index = fs::read(segment_path(dir, sequence, "idx"))
+            .ok()
+            .and_then(|index| serde_json::from_slice::<SegmentIndex>(&index).ok());
+
+        let segment = match index {
+            Some(index) => Segment {
+                sequence,
+                size: fs::metadata(&path)?.len(),
+                index,
+                is_sealed: true,
+            },
+            None => match read_segment(&path, |_| {}) {
+                Ok((index, valid_len)) => Segment {
+                    sequence,
+                    size: valid_len,
+                    index,
+                    is_sealed: false,
+                },
+                Err(err) => {
+                    warn!("Deleting unreadable segment {:?}! {}", path, err);
+                    delete_segment(dir, sequence);
+                    continue;
+                }
+            },
+        };
+
+        segments.push_back(segment);
+    }
+
+    Ok(segments)
+}
+
+// Only passes on the records of complete batches. Returns the index of the segment and the length of its
+// complete batches.
+fn read_segment(path: &Path, mut on_record: impl FnMut(Record)) -> io::Result<(SegmentIndex, u64)> {
+    let data = fs::read(path)?;
+    let mut rest = data
+        .strip_prefix(SEGMENT_HEADER)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unknown segment format"))?;
+    let mut index = SegmentIndex::new();
+    let mut valid_len = SEGMENT_HEADER.len();
+    let mut batch = Vec::new();
+
+    // Stops at the first incomplete record, which is left over when the server stopped while writing
+    while let Some(record) = read_record(&mut rest) {
+        let is_commit = matches!(record, Record::Commit(..));
+        batch.push(record);
+
+        if is_commit {
+            for record in batch.drain(..) {
+                if let Record::Entry(entry) = &record {
+                    index.add(entry.timestamp);
+                }
+
+                on_record(record);
+            }
+
+            valid_len = data.len() - rest.len();
+        }
+    }
+
+    Ok((index, valid_len as u64))
+}
+
+fn read_record<'a>(data: &mut &'a [u8]) -> Option<Record<'a>> {
+    let mut rest = *data;
+
+    let record = match take_bytes(&mut rest, 1)?[0] {
+        ENTRY_RECORD => Record::Entry(RawEntry {
+            timestamp: i64::from_le_bytes(take_bytes(&mut rest, 8)?.try_into().ok()?),
+            offset: i32::from_le_bytes(take_bytes(&mut rest, 4)?.try_into().ok()?),
+            level: take_bytes(&mut rest, 1)?[0],
+            module: take_str(&mut rest)?,
+            message: take_str(&mut rest)?,
+            fields: take_str(&mut rest)?,
+        }),
+        APPEND_RECORD => Record::Append(
+            u64::from_le_bytes(take_bytes(&mut rest, 8)?.try_into().ok()?) as usize,
+            take_str(&mut rest)?,
+        ),
+        SOURCE_RECORD => Record::Source(u64::from_le_bytes(
+            take_bytes(&mut rest, 8)?.try_into().ok()?,
+        )),
+        COMMIT_RECORD => Record::Commit(
+            u64::from_le_bytes(take_bytes(&mut rest, 8)?.try_into().ok()?) as usize,
+            take_str(&mut rest)?,
+        ),
+        _ => return None,
+    };
+
+    *data = rest;
+    Some(record)
+}
+
+fn take_bytes<'a>(data: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    let bytes = data.get(..len)?;
+    *data = &data[len..];
+    Some(bytes)
+}
+
+fn take_str<'a>(data: &mut &'a [u8]) -> Option<&'a str> {
+    let len = u32::from_le_bytes(take_bytes(data, 4)?.try_into().ok()?);
+    std::str::from_utf8(take_bytes(data, len as usize)?).ok()
+}
+
+fn create_segment(dir: &Path, sequence: u64) -> io::Result<(File, Segment)> {
+    let mut file = OpenOptions::new()
+        .append(true)
+        .create_new(true)
+        .open(segment_path(dir, sequence, "seg"))?;
+    file.write_all(SEGMENT_HEADER)?;
+
+    let segment = Segment {
+        sequence,
+        size: SEGMENT_HEADER.len() as u64,
+        index: SegmentIndex::new(),
+        is_sealed: false,
+    };
+
+    Ok((file, segment))
+}
+
+fn delete_segment(dir: &Path, sequence: u64) {
+    for extension in ["idx", "seg"] {
+        let path = segment_path(dir, sequence, extension);
+
+        if let Err(err) = fs::remove_file(&path) {
+            if err.kind() != io::ErrorKind::NotFound {
+                error!("Failed to delete {:?}! {}", path, err);
+            }
+        }
+    }
+}
+
+fn segment_path(dir: &Path, sequence: u64, extension: &str) -> PathBuf {
+    dir.join(format!("{:010}.{}", sequence, extension))
+}
+
+// Application names can contain anything, so everything but letters, digits, '-' and '_' is percent-encoded
+fn encode_app_name(app_name: &str) -> String {
+    let mut encoded = String::new();
+
+    for byte in app_name.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use time::OffsetDateTime;
+
+    use super::*;
+    use crate::LogEntry;
+
+    // Removed again when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "logpeek-store-test-{}-{}",
+                std::process::id(),
+                name
+            ));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn writer(dir: &Path) -> SegmentWriter {
+        let (file, current) = create_segment(dir, 0).unwrap();
+
+        SegmentWriter {
+            dir: dir.to_path_buf(),
+            file,
+            current,
+            sealed: VecDeque::new(),
+            pending: Vec::new(),
+            retention: None,
+            read_states: Vec::new(),
+        }
+    }
+
+    fn entry(timestamp: i64, message: &str) -> LogEntry {
+        LogEntry {
+            timestamp: OffsetDateTime::from_unix_timestamp(timestamp).unwrap(),
+            level: log::Level::Warn,
+            module: "module".to_string(),
+            message: message.to_string(),
+            fields: BTreeMap::from([("key".to_string(), "value".to_string())]),
+            application: 0,
+            parse_failed: false,
+        }
+    }
+
+    fn restore(dir: &Path) -> LogBuffer {
+        restore_with_read_states(dir).0
+    }
+
+    fn restore_with_read_states(dir: &Path) -> (LogBuffer, Vec<String>) {
+        let mut segments = list_segments(dir).unwrap();
+        let mut log_buffer = LogBuffer::new(1000, None, None, 0);
+        let read_states = replay(dir, &mut segments, &mut log_buffer);
+        (log_buffer, read_states)
+    }
+
+    fn messages(log_buffer: &LogBuffer) -> Vec<String> {
+        (0..log_buffer.len())
+            .map(|i| log_buffer.get(i).unwrap().message.to_string())
+            .collect()
+    }
+
+    // The bytes of a record, as they would be written by the next commit
+    fn record_bytes(writer: &mut SegmentWriter, timestamp: i64, message: &str) -> Vec<u8> {
+        let mut log_buffer = LogBuffer::new(1, None, None, 0);
+        log_buffer.push(entry(timestamp, message));
+        writer.write_entry(&RawEntry {
+            timestamp: log_buffer.timestamp_nanos(0).unwrap(),
+            offset: 0,
+            level: log::Level::Info as u8,
+            module: "module",
+            message,
+            fields: "",
+        });
+        std::mem::take(&mut writer.pending)
+    }
+
+    fn append_to_segment(dir: &Path, bytes: &[u8]) {
+        OpenOptions::new()
+            .append(true)
+            .open(segment_path(dir, 0, "seg"))
+            .unwrap()
+            .write_all(bytes)
+            .unwrap();
+    }
+
+    #[test]
+    fn replay_batches() {
+        let dir = TempDir::new("batches");
+        let mut log_buffer = LogBuffer::new(1000, None, None, 0);
+        log_buffer.store = Some(writer(&dir.0));
+
+        // Out of order, with a continuation line of an entry that isn't the newest
+        log_buffer.push(entry(3, "c"));
+        log_buffer.push(entry(1, "a"));
+        log_buffer.push(entry(2, "b"));
+        log_buffer.append_to_message(1, "continued");
+        log_buffer.commit(3);
+
+        log_buffer.push(entry(4, "d"));
+        log_buffer.commit(1);
+
+        let restored = restore(&dir.0);
+        assert_eq!(messages(&restored), ["a\ncontinued", "b", "c", "d"]);
+        assert_eq!(messages(&restored), messages(&log_buffer));
+
+        for i in 0..restored.len() {
+            let (original, restored) = (log_buffer.get(i).unwrap(), restored.get(i).unwrap());
+            assert_eq!(original.timestamp(), restored.timestamp());
+            assert_eq!(original.level, restored.level);
+            assert_eq!(original.module, restored.module);
+            assert_eq!(restored.field("key"), Some("value"));
+        }
+    }
+
+    #[test]
+    fn replay_uncommitted_entries() {
+        let dir = TempDir::new("uncommitted");
+        let mut writer = writer(&dir.0);
+
+        let mut log_buffer = LogBuffer::new(1000, None, None, 0);
+        log_buffer.store = Some(writer);
+        log_buffer.push(entry(2, "b"));
+        log_buffer.commit(1);
+        writer = log_buffer.store.take().unwrap();
+
+        // Written without the commit record, when the server stopped in the middle of a batch. The read state of
+        // the batch wasn't stored either, so its entries will be read again.
+        let valid_len = writer.current.size;
+        let uncommitted = record_bytes(&mut writer, 1, "a");
+        append_to_segment(&dir.0, &uncommitted);
+
+        assert_eq!(messages(&restore(&dir.0)), ["b"]);
+        let segments = list_segments(&dir.0).unwrap();
+        assert_eq!(segments[0].size, valid_len);
+        assert_eq!(segments[0].index.entries, 1);
+    }
+
+    #[test]
+    fn replay_last_entries() {
+        let dir = TempDir::new("sources");
+        let mut log_buffer = LogBuffer::new(1000, None, None, 0);
+        log_buffer.store = Some(writer(&dir.0));
+
+        log_buffer.push_from(7, entry(2, "b"));
+        log_buffer.push_from(8, entry(1, "a"));
+        log_buffer.commit(2);
+        log_buffer.push_from(7, entry(3, "c"));
+        log_buffer.push_from(9, entry(4, "d"));
+        log_buffer.commit(2);
+
+        // Continuation lines after a restart go to the same entries as before
+        let mut restored = restore(&dir.0);
+        for source in [7, 8, 9, 10] {
+            assert_eq!(restored.last_entry(source), log_buffer.last_entry(source));
+        }
+
+        let i = restored.last_entry(8).unwrap();
+        restored.append_to_message(i, "continued");
+        assert_eq!(messages(&restored), ["a\ncontinued", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn replay_read_states() {
+        let dir = TempDir::new("read-states");
+        let mut log_buffer = LogBuffer::new(1000, None, None, 0);
+        log_buffer.store = Some(writer(&dir.0));
+
+        log_buffer.push(entry(1, "a"));
+        log_buffer.commit_read_state(1, "first");
+        log_buffer.push(entry(2, "b"));
+        log_buffer.commit(1);
+        log_buffer.push(entry(3, "c"));
+        log_buffer.commit_read_state(1, "second");
+
+        // Batches without entries aren't stored
+        log_buffer.commit_read_state(0, "empty");
+
+        let (restored, read_states) = restore_with_read_states(&dir.0);
+        assert_eq!(messages(&restored), ["a", "b", "c"]);
+        assert_eq!(read_states, ["first", "second"]);
+    }
+
+    #[test]
+    fn replay_truncated_record() {
+        let dir = TempDir::new("truncated");
+        let mut log_buffer = LogBuffer::new(1000, None, None, 0);
+        log_buffer.store = Some(writer(&dir.0));
+        log_buffer.push(entry(1, "a"));
+        log_buffer.commit(1);
+
+        let mut writer = log_buffer.store.take().unwrap();
+        let valid_len = writer.current.size;
+        let record = record_bytes(&mut writer, 2, "cut off");
+        append_to_segment(&dir.0, &record[..record.len() - 3]);
+
+        let segments = list_segments(&dir.0).unwrap();
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].size, valid_len);
+        assert_eq!(segments[0].index.entries, 1);
+        assert!(!segments[0].is_sealed);
+        assert_eq!(messages(&restore(&dir.0)), ["a"]);
+    }
+
+    #[test]
+    fn read_incomplete_records() {
+        let dir = TempDir::new("incomplete");
+        let mut writer = writer(&dir.0);
+        let mut records = record_bytes(&mut writer, 1, "message");
+        writer.write_append(0, "line");
+        writer.write_source(7);
+        writer.commit(1, "state", 1);
+        records.extend(
+            fs::read(segment_path(&dir.0, 0, "seg")).unwrap()[SEGMENT_HEADER.len()..].iter(),
+        );
+
+        let mut complete = records.as_slice();
+        let mut record_ends = Vec::new();
+        while read_record(&mut complete).is_some() {
+            record_ends.push(records.len() - complete.len());
+        }
+        assert_eq!(record_ends.len(), 4);
+        assert!(complete.is_empty());
+
+        // Reading stops at the end of the last complete record
+        for len in 0..records.len() {
+            let mut data = &records[..len];
+            while read_record(&mut data).is_some() {}
+
+            let last_end = record_ends.iter().rev().find(|end| **end <= len);
+            assert_eq!(len - data.len(), last_end.copied().unwrap_or(0));
+        }
+
+        // Unknown record types and invalid UTF-8 end the segment
+        assert!(read_record(&mut &[7, 0, 0, 0][..]).is_none());
+        let mut invalid_utf8 = vec![APPEND_RECORD];
+        invalid_utf8.extend_from_slice(&0u64.to_le_bytes());
+        invalid_utf8.extend_from_slice(&2u32.to_le_bytes());
+        invalid_utf8.extend_from_slice(&[0xc3, 0x28]);
+        assert!(read_record(&mut invalid_utf8.as_slice()).is_none());
+    }
+
+    #[test]
+    fn unknown_segment_format() {
+        let dir = TempDir::new("format");
+        fs::write(segment_path(&dir.0, 0, "seg"), b"LPSEG001").unwrap();
+        fs::write(segment_path(&dir.0, 1, "seg"), b"").unwrap();
+
+        let err = read_segment(&segment_path(&dir.0, 0, "seg"), |_| {}).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // Unreadable segments are deleted
+        assert!(list_segments(&dir.0).unwrap().is_empty());
+        assert!(!segment_path(&dir.0, 0, "seg").exists());
+    }
+
+    #[test]
+    fn append_past_the_buffer() {
+        let dir = TempDir::new("append");
+        let mut writer = writer(&dir.0);
+        writer.pending = record_bytes(&mut writer, 1, "a");
+        writer.write_append(5, "ignored");
+        writer.commit(1, "", 1);
+
+        assert_eq!(messages(&restore(&dir.0)), ["a"]);
+    }
+
+    #[test]
+    fn delete_evicted_segments() {
+        let dir = TempDir::new("delete");
+        let segment_exists = |sequence| segment_path(&dir.0, sequence, "seg").exists();
+
+        // Budgets in bytes don't matter, only how many entries the buffer still holds
+        let mut log_buffer = LogBuffer::new(2, Some(1_000_000), None, 0);
+        log_buffer.store = Some(writer(&dir.0));
+
+        for (timestamp, message) in [(1, "a"), (2, "b")] {
+            log_buffer.push(entry(timestamp, message));
+            log_buffer.commit(1);
+            log_buffer.store.as_mut().unwrap().seal().unwrap();
+        }
+        assert!(segment_exists(0) && segment_exists(1));
+
+        log_buffer.push(entry(3, "c"));
+        log_buffer.commit(1);
+        assert!(!segment_exists(0));
+        assert!(segment_exists(1) && segment_exists(2));
+        assert_eq!(messages(&restore(&dir.0)), ["b", "c"]);
+
+        // The current segment holds all of the entries after the next batch
+        log_buffer.push(entry(4, "d"));
+        log_buffer.commit(1);
+        assert!(!segment_exists(1));
+        assert_eq!(messages(&restore(&dir.0)), ["c", "d"]);
+    }
+
+    #[test]
+    fn app_names() {
+        assert_eq!(encode_app_name("my-app_1"), "my-app_1");
+        assert_eq!(
+            encode_app_name("syslog 0.0.0.0:514"),
+            "syslog%200%2E0%2E0%2E0%3A514"
+        );
+        assert_eq!(encode_app_name("../ä"), "%2E%2E%2F%C3%A4");
+    }
+}
@@ -0,0 +1,696 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Once};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use config::{Value, ValueKind};
+use glob::{glob, MatchOptions, Pattern};
+use log::{error, warn};
+use regex::Regex;
+use time::format_description;
+use time_tz::{timezones, Tz};
+
+use crate::config::{parse_byte_size, parse_duration};
+use crate::log_reader::encoding::Encoding;
+use crate::log_reader::presets::apply_preset;
+use crate::log_reader::{FieldNames, LogFormat, Multiline, ParseErrorPolicy, TimeFormat};
+use crate::SETTINGS;
+
+// Everything configured for a single [[application]] entry
+pub struct Application {
+    pub name: String,
+    // Applications without a path only receive logs through the ingest endpoint
+    pub path: Option<String>,
+    // Filters for the files found in the path, when it is a directory
+    pub include: Vec<Pattern>,
+    pub exclude: Vec<Pattern>,
+    pub format: LogFormat,
+    pub encoding: Encoding,
+    // Replace invalid byte sequences instead of dropping the line
+    pub lossy: bool,
+    pub timeformat: TimeFormat,
+    // Used for timestamps without an offset
+    pub timezone: Option<&'static Tz>,
+    pub level_map: Option<HashMap<String, String>>,
+    pub multiline: Option<Multiline>,
+    pub multiline_max_size: usize,
+    pub on_parse_error: ParseErrorPolicy,
+    pub buffer_size: u64,
+    // Limits the buffer by the memory its entries take up, in addition to their number
+    pub buffer_bytes: Option<u64>,
+    // Entries older than this are evicted as well
+    pub retention: Option<Duration>,
+}
+
+impl Application {
+    pub fn from_config(app: Value) -> Result<Self> {
+        let mut app_table = app
+            .into_table()
+            .context("Config file is formatted incorrectly!")?;
+
+        if let Some(preset) = app_table
+            .get("preset")
+            .map(|preset| {
+                preset
+                    .clone()
+                    .into_string()
+                    .context("Preset is not a string!")
+            })
+            .transpose()?
+        {
+            apply_preset(&mut app_table, &preset)?;
+        }
+
+        let path = app_table
+            .get("path")
+            .map(|path| path.clone().into_string().context("Path is not a string!"))
+            .transpose()?;
+
+        let name = app_table
+            .get("name")
+            .map(|name| name.clone().into_string().context("Name is not a string!"))
+            .transpose()?
+            .or_else(|| path.clone())
+            .context("An application needs either a path or a name in the config!")?;
+
+        let include = get_patterns(&app_table, "include")?;
+        let exclude = get_patterns(&app_table, "exclude")?;
+
+        let level_map: Option<HashMap<String, String>> = app_table
+            .get("level_map")
+            .and_then(|level_map| level_map.clone().into_table().ok())
+            .map(|table| {
+                table
+                    .into_iter()
+                    .filter_map(|(k, v)| v.into_string().ok().map(|val| (k, val)))
+                    .collect()
+            });
+
+        let configured_format = app_table
+            .get("format")
+            .map(|format| {
+                format
+                    .clone()
+                    .into_string()
+                    .context("Format is not a string!")
+            })
+            .transpose()?
+            .unwrap_or_else(|| "regex".to_string());
+
+        let format = match configured_format.as_str() {
+            "regex" => LogFormat::Regex(
+                Regex::new(
+                    &app_table
+                        .get("parser")
+                        .context("An application is missing the parser field in the config!")?
+                        .clone()
+                        .into_string()
+                        .context("Parser is not a string!")?,
+                )
+                .context("Failed to compile regex!")?,
+            ),
+            "json" => LogFormat::Json(FieldNames {
+                timestamp: get_field_name(&app_table, "timestamp_field", "timestamp")?,
+                level: get_field_name(&app_table, "level_field", "level")?,
+                module: get_field_name(&app_table, "module_field", "module")?,
+                message: get_field_name(&app_table, "message_field", "message")?,
+            }),
+            "logfmt" => LogFormat::Logfmt(FieldNames {
+                timestamp: get_field_name(&app_table, "timestamp_field", "ts")?,
+                level: get_field_name(&app_table, "level_field", "level")?,
+                module: get_field_name(&app_table, "module_field", "module")?,
+                message: get_field_name(&app_table, "message_field", "msg")?,
+            }),
+            // The regex parser is optional for container logs and applies to the unwrapped message
+            "docker" => LogFormat::Docker(get_parser(&app_table)?),
+            "cri" => LogFormat::Cri(get_parser(&app_table)?),
+            "journal_json" => LogFormat::JournalJson,
+            "journal_export" => LogFormat::JournalExport,
+            unknown_format => bail!("Unknown log format: {}", unknown_format),
+        };
+
+        let encoding = match app_table.get("encoding") {
+            Some(encoding) => Encoding::from_label(
+                &encoding
+                    .clone()
+                    .into_string()
+                    .context("Encoding is not a string!")?,
+            )?,
+            None => Encoding::Utf8,
+        };
+
+        let lossy = app_table
+            .get("lossy")
+            .map(|lossy| lossy.clone().into_bool().context("lossy is not a boolean!"))
+            .transpose()?
+            .unwrap_or(false);
+
+        let configured_timeformat = if let Some(configured_timeformat) = app_table.get("timeformat")
+        {
+            configured_timeformat.clone().to_string()
+        } else {
+            "iso8601".to_string()
+        };
+
+        let timeformat = match configured_timeformat.as_str() {
+            "iso8601" => TimeFormat::Iso8601,
+            "rfc3339" => TimeFormat::Rfc3339,
+            "rfc2822" => TimeFormat::Rfc2822,
+            "epoch_s" => TimeFormat::EpochS,
+            "epoch_ms" => TimeFormat::EpochMs,
+            "epoch_ns" => TimeFormat::EpochNs,
+            custom_format_str => {
+                let format_desc = format_description::parse_owned::<1>(custom_format_str)
+                    .context("Invalid custom time format!")?;
+                TimeFormat::Custom(format_desc)
+            }
+        };
+
+        let timezone = match app_table.get("timezone") {
+            Some(timezone) => {
+                let timezone = timezone
+                    .clone()
+                    .into_string()
+                    .context("Timezone is not a string!")?;
+                Some(
+                    timezones::get_by_name(&timezone)
+                        .with_context(|| format!("Unknown timezone: {}", timezone))?,
+                )
+            }
+            None => None,
+        };
+
+        let multiline = match app_table.get("multiline") {
+            Some(multiline) => Some(
+                match multiline
+                    .clone()
+                    .into_string()
+                    .context("multiline is not a string!")?
+                    .as_str()
+                {
+                    "unmatched" => Multiline::Unmatched,
+                    continuation_pattern => Multiline::Continuation(
+                        Regex::new(continuation_pattern)
+                            .context("Failed to compile multiline regex!")?,
+                    ),
+                },
+            ),
+            None => None,
+        };
+
+        let multiline_max_size = app_table
+            .get("multiline_max_size")
+            .unwrap_or(&Value::new(None, ValueKind::U64(65_536)))
+            .clone()
+            .into_uint()
+            .context("multiline_max_size is not parsable to an unsigned integer!")?
+            as usize;
+
+        let on_parse_error = match app_table
+            .get("on_parse_error")
+            .map(|policy| {
+                policy
+                    .clone()
+                    .into_string()
+                    .context("on_parse_error is not a string!")
+            })
+            .transpose()?
+            .as_deref()
+        {
+            Some(policy) => ParseErrorPolicy::from_label(policy)?,
+            None => ParseErrorPolicy::Drop,
+        };
+
+        let buffer_bytes = match app_table.get("buffer_bytes") {
+            Some(buffer_bytes) => {
+                let buffer_bytes = buffer_bytes
+                    .clone()
+                    .into_string()
+                    .context("buffer_bytes is not a string!")?;
+
+                Some(
+                    parse_byte_size(&buffer_bytes)
+                        .with_context(|| format!("Invalid buffer_bytes: {}", buffer_bytes))?,
+                )
+            }
+            None => None,
+        };
+
+        let retention = match app_table.get("retention") {
+            Some(retention) => {
+                let retention = retention
+                    .clone()
+                    .into_string()
+                    .context("Retention is not a string!")?;
+
+                Some(
+                    parse_duration(&retention)
+                        .with_context(|| format!("Invalid retention: {}", retention))?,
+                )
+            }
+            None => None,
+        };
+
+        // The number of entries is only limited by default when there is no limit on their size
+        let default_buffer_size = if buffer_bytes.is_some() {
+            u64::MAX
+        } else {
+            1_000_000
+        };
+
+        let buffer_size = app_table
+            .get("buffer_size")
+            .unwrap_or(&Value::new(None, ValueKind::U64(default_buffer_size)))
+            .clone()
+            .into_uint()
+            .context("buffer_size is not parsable to an unsigned integer!")?;
+
+        Ok(Application {
+            name,
+            path,
+            include,
+            exclude,
+            format,
+            encoding,
+            lossy,
+            timeformat,
+            timezone,
+            level_map,
+            multiline,
+            multiline_max_size,
+            on_parse_error,
+            buffer_size,
+            buffer_bytes,
+            retention,
+        })
+    }
+
+    // The path is relative to the application's directory. Patterns without a slash are matched against the file name,
+    // e.g. "*.log", others against the whole relative path, e.g. "archive/**".
+    pub fn is_included(&self, relative_path: &Path) -> bool {
+        let matches = |pattern: &Pattern| {
+            if pattern.as_str().contains('/') {
+                pattern.matches_path_with(
+                    relative_path,
+                    MatchOptions {
+                        require_literal_separator: true,
+                        ..Default::default()
+                    },
+                )
+            } else {
+                relative_path
+                    .file_name()
+                    .and_then(|file_name| file_name.to_str())
+                    .is_some_and(|file_name| pattern.matches(file_name))
+            }
+        };
+
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+}
+
+// Each [[application_template]] creates an application for every path matching its {name} placeholder,
+// all of them sharing the rest of the template's configuration
+pub struct Template {
+    table: HashMap<String, Value>,
+    // Without the trailing slash, glob doesn't return directories with it
+    path: String,
+    name: String,
+    path_re: Regex,
+    // Declared with a trailing slash, otherwise files match as well
+    only_dirs: bool,
+}
+
+// Mistakes in the [[application]] sections are returned as errors, the same as for the rest of the settings.
+// Applications created by templates are skipped instead, as their paths can appear at any time.
+pub fn get_applications(templates: &[Template]) -> Result<Vec<Application>> {
+    let mut applications: Vec<Application> = match SETTINGS.get_array("application") {
+        Ok(applications) => applications
+            .into_iter()
+            .map(Application::from_config)
+            .collect::<Result<_>>()?,
+        Err(_) if !templates.is_empty() => Vec::new(),
+        Err(_) => vec![Application::from_config(Value::new(
+            None,
+            create_default_map(),
+        ))?],
+    };
+
+    let mut names = applications.iter().map(|app| app.name.clone()).collect();
+    applications.extend(get_templated_applications(templates, &mut names)?);
+    Ok(applications)
+}
+
+// Paths watched for changes. The directories containing the {name} placeholder of templates are watched as well,
+// so that applications are also created for directories appearing later on.
+pub fn get_watch_paths(applications: &[Arc<Application>], templates: &[Template]) -> Vec<String> {
+    applications
+        .iter()
+        .filter_map(|app| app.path.clone())
+        .chain(templates.iter().map(Template::base_path))
+        .collect()
+}
+
+// Names that are already taken, by the configured applications or those of an earlier template, are skipped
+fn get_templated_applications(
+    templates: &[Template],
+    names: &mut HashSet<String>,
+) -> Result<Vec<Application>> {
+    let mut applications = Vec::new();
+
+    for template in templates {
+        let paths = glob(&template.path.replace("{name}", "*"))
+            .with_context(|| format!("Invalid application template path: {}", template.path))?;
+
+        for path in paths.flatten() {
+            if template.only_dirs && !path.is_dir() {
+                continue;
+            }
+
+            let path = path.to_string_lossy().into_owned();
+            let name = match template.application_name(&path) {
+                Some(name) => name,
+                None => continue,
+            };
+
+            if !names.insert(name.clone()) {
+                warn!(
+                    "Skipping {} of template {}, there already is an application named {}",
+                    path, template.path, name
+                );
+                continue;
+            }
+
+            match template.create_application(&name, &path) {
+                Ok(app) => applications.push(app),
+                Err(err) => error!(
+                    "Skipping application {} of template {}! {:#}",
+                    name, template.path, err
+                ),
+            }
+        }
+    }
+
+    Ok(applications)
+}
+
+// Applications for the paths of the watcher's events that match a template, e.g. a directory that was just created.
+// Only the changed paths and their parents are looked at, paths whose name is already taken are skipped.
+pub fn find_templated_applications(
+    templates: &[Template],
+    changed_paths: &HashSet<PathBuf>,
+    applications: &[Arc<Application>],
+) -> Vec<Application> {
+    let mut names: HashSet<String> = applications.iter().map(|app| app.name.clone()).collect();
+    let mut new_applications = Vec::new();
+
+    for template in templates {
+        let base_path = template.base_path();
+
+        // Event paths start with the canonical path that is watched
+        let canonical_base_path = match fs::canonicalize(&base_path) {
+            Ok(canonical_base_path) => canonical_base_path,
+            Err(_) => continue,
+        };
+
+        for changed_path in changed_paths {
+            let relative_path = match changed_path.strip_prefix(&canonical_base_path) {
+                Ok(relative_path) => relative_path,
+                Err(_) => continue,
+            };
+
+            // Translated back to the configured path, the same as the paths found by glob
+            let mut path = match base_path.as_str() {
+                "." => PathBuf::new(),
+                base_path => PathBuf::from(base_path),
+            };
+
+            for component in relative_path.components() {
+                path.push(component);
+
+                let path = path.to_string_lossy().into_owned();
+                let name = match template.application_name(&path) {
+                    Some(name) if !names.contains(&name) => name,
+                    _ => continue,
+                };
+
+                if template.only_dirs && !Path::new(&path).is_dir() {
+                    continue;
+                }
+
+                match template.create_application(&name, &path) {
+                    Ok(app) => {
+                        names.insert(name);
+                        new_applications.push(app);
+                    }
+                    Err(err) => error!(
+                        "Skipping application {} of template {}! {:#}",
+                        name, template.path, err
+                    ),
+                }
+            }
+        }
+    }
+
+    new_applications
+}
+
+pub fn get_templates() -> Result<Vec<Template>> {
+    SETTINGS
+        .get_array("application_template")
+        .unwrap_or_default()
+        .into_iter()
+        .map(Template::from_config)
+        .collect()
+}
+
+impl Template {
+    // The shared configuration is checked right away, so that mistakes in it don't only show up once a path matches
+    fn from_config(template: Value) -> Result<Self> {
+        let table = template
+            .into_table()
+            .context("Config file is formatted incorrectly!")?;
+
+        let configured_path = table
+            .get("path")
+            .context("An application template is missing the required path in the config!")?
+            .clone()
+            .into_string()
+            .context("Path is not a string!")?;
+
+        if !configured_path.contains("{name}") {
+            bail!(
+                "The path of an application template must contain {{name}}: {}",
+                configured_path
+            );
+        }
+
+        let name = table
+            .get("name")
+            .map(|name| name.clone().into_string().context("Name is not a string!"))
+            .transpose()?
+            .unwrap_or_else(|| "{name}".to_string());
+
+        let path = configured_path.trim_end_matches('/').to_string();
+        let path_re = Regex::new(&format!(
+            "^{}$",
+            path.split("{name}")
+                .map(regex::escape)
+                .collect::<Vec<_>>()
+                .join("([^/]+)")
+        ))
+        .context("Failed to compile template path regex!")?;
+
+        let template = Template {
+            table,
+            only_dirs: configured_path.ends_with('/'),
+            path,
+            name,
+            path_re,
+        };
+
+        template
+            .create_application(&template.name, &template.path)
+            .with_context(|| format!("Invalid application template {}", template.path))?;
+        Ok(template)
+    }
+
+    fn base_path(&self) -> String {
+        let (base_path, _) = self
+            .path
+            .split_once("{name}")
+            .expect("Template path was checked for {name}");
+
+        match base_path.rsplit_once('/') {
+            Some(("", _)) => "/".to_string(),
+            Some((base_path, _)) => base_path.to_string(),
+            None => ".".to_string(),
+        }
+    }
+
+    // A placeholder that is used more than once has to stand for the same name each time
+    fn application_name(&self, path: &str) -> Option<String> {
+        let captures = self.path_re.captures(path)?;
+        let mut names = captures.iter().skip(1).flatten().map(|name| name.as_str());
+        let name = names.next()?;
+
+        names
+            .all(|other_name| other_name == name)
+            .then(|| self.name.replace("{name}", name))
+    }
+
+    fn create_application(&self, name: &str, path: &str) -> Result<Application> {
+        let mut app_table = self.table.clone();
+        app_table.insert(
+            "name".to_string(),
+            Value::new(None, ValueKind::String(name.to_string())),
+        );
+        app_table.insert(
+            "path".to_string(),
+            Value::new(None, ValueKind::String(path.to_string())),
+        );
+
+        Application::from_config(Value::new(None, ValueKind::Table(app_table)))
+    }
+}
+
+fn get_patterns(app_table: &HashMap<String, Value>, key: &str) -> Result<Vec<Pattern>> {
+    let patterns = match app_table.get(key) {
+        Some(patterns) => patterns
+            .clone()
+            .into_array()
+            .with_context(|| format!("{} is not an array!", key))?,
+        None => return Ok(Vec::new()),
+    };
+
+    patterns
+        .into_iter()
+        .map(|pattern| {
+            let pattern = pattern
+                .into_string()
+                .with_context(|| format!("{} contains a pattern that is not a string!", key))?;
+            Pattern::new(&pattern)
+                .map_err(|err| anyhow!("Invalid {} pattern {}! {}", key, pattern, err))
+        })
+        .collect()
+}
+
+fn get_parser(app_table: &HashMap<String, Value>) -> Result<Option<Regex>> {
+    app_table
+        .get("parser")
+        .map(|parser| {
+            Regex::new(
+                &parser
+                    .clone()
+                    .into_string()
+                    .context("Parser is not a string!")?,
+            )
+            .context("Failed to compile regex!")
+        })
+        .transpose()
+}
+
+fn get_field_name(app_table: &HashMap<String, Value>, key: &str, default: &str) -> Result<String> {
+    match app_table.get(key) {
+        Some(field) => field
+            .clone()
+            .into_string()
+            .with_context(|| format!("{} is not a string!", key)),
+        None => Ok(default.to_string()),
+    }
+}
+
+fn create_default_map() -> ValueKind {
+    // The applications are read again on every reload
+    static WARN_ONCE: Once = Once::new();
+    WARN_ONCE.call_once(|| warn!("No application configurations found, proceeding with defaults."));
+
+    let mut map = HashMap::new();
+    map.insert(
+        "path".to_string(),
+        Value::new(None, ValueKind::String("logpeek-logs".to_string())),
+    );
+    map.insert(
+        "preset".to_string(),
+        Value::new(None, ValueKind::String("logpeek".to_string())),
+    );
+    map.insert(
+        "buffer_size".to_string(),
+        Value::new(None, ValueKind::U64(1_000_000)),
+    );
+
+    ValueKind::Table(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(fields: &[(&str, &str)]) -> Result<Template> {
+        let mut table: HashMap<String, Value> = fields
+            .iter()
+            .map(|(key, value)| {
+                (
+                    key.to_string(),
+                    Value::new(None, ValueKind::String(value.to_string())),
+                )
+            })
+            .collect();
+        table
+            .entry("parser".to_string())
+            .or_insert_with(|| Value::new(None, ValueKind::String("^(?P<message>.+)$".into())));
+
+        Template::from_config(Value::new(None, ValueKind::Table(table)))
+    }
+
+    #[test]
+    fn template_paths() {
+        let dirs = template(&[
+            ("path", "/var/log/services/{name}/"),
+            ("name", "service-{name}"),
+        ])
+        .unwrap();
+        assert!(dirs.only_dirs);
+        assert_eq!(dirs.base_path(), "/var/log/services");
+        assert_eq!(
+            dirs.application_name("/var/log/services/db").as_deref(),
+            Some("service-db")
+        );
+        assert_eq!(dirs.application_name("/var/log/services/db/current"), None);
+        assert_eq!(dirs.application_name("/var/log/other/db"), None);
+
+        let files = template(&[("path", "logs/{name}.log")]).unwrap();
+        assert!(!files.only_dirs);
+        assert_eq!(files.base_path(), "logs");
+        assert_eq!(
+            files.application_name("logs/api.log").as_deref(),
+            Some("api")
+        );
+        assert_eq!(files.application_name("logs/api.txt"), None);
+    }
+
+    #[test]
+    fn template_repeated_placeholder() {
+        let template = template(&[("path", "/srv/{name}/logs/{name}.log")]).unwrap();
+
+        assert_eq!(
+            template
+                .application_name("/srv/api/logs/api.log")
+                .as_deref(),
+            Some("api")
+        );
+        assert_eq!(template.application_name("/srv/api/logs/db.log"), None);
+    }
+
+    #[test]
+    fn template_malformed() {
+        assert!(template(&[]).is_err());
+        assert!(template(&[("path", "/var/log/services/")]).is_err());
+        assert!(template(&[("path", "/srv/{name}/"), ("parser", "(unclosed")]).is_err());
+        assert!(template(&[("path", "/srv/{name}/"), ("format", "yaml")]).is_err());
+        assert!(Template::from_config(Value::new(None, ValueKind::String("path".into()))).is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use flate2::read::MultiGzDecoder;
+use xz2::read::XzDecoder;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const XZ_MAGIC: &[u8] = &[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00];
+
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl Compression {
+    // Magic bytes take precedence, the extension is only used when the file is too short to tell
+    fn detect(file: &mut File, path: &Path) -> io::Result<Self> {
+        let mut magic = [0; 6];
+        let mut magic_len = 0;
+
+        while magic_len < magic.len() {
+            match file.read(&mut magic[magic_len..])? {
+                0 => break,
+                bytes_read => magic_len += bytes_read,
+            }
+        }
+
+        file.seek(SeekFrom::Start(0))?;
+
+        let magic = &magic[..magic_len];
+        let compression = if magic.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else if magic.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else if magic.starts_with(XZ_MAGIC) {
+            Compression::Xz
+        } else if magic_len < XZ_MAGIC.len() {
+            match path.extension().and_then(|extension| extension.to_str()) {
+                Some("gz") => Compression::Gzip,
+                Some("zst") => Compression::Zstd,
+                Some("xz") => Compression::Xz,
+                _ => Compression::None,
+            }
+        } else {
+            Compression::None
+        };
+
+        Ok(compression)
+    }
+}
+
+// Opens a log file, transparently decompressing it if needed.
+// The offset refers to the decompressed contents.
+pub fn open(
+    path: &Path,
+    offset: u64,
+) -> io::Result<(BufReader<Box<dyn Read + Send>>, Compression)> {
+    let mut file = File::open(path)?;
+    let compression = Compression::detect(&mut file, path)?;
+
+    let decoder: Box<dyn Read + Send> = match compression {
+        Compression::None => {
+            file.seek(SeekFrom::Start(offset))?;
+            Box::new(file)
+        }
+        Compression::Gzip => Box::new(MultiGzDecoder::new(BufReader::new(file))),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(file)?),
+        Compression::Xz => Box::new(XzDecoder::new_multi_decoder(BufReader::new(file))),
+    };
+    let mut reader = BufReader::with_capacity(READ_BUFFER_SIZE, decoder);
+
+    if compression != Compression::None {
+        // Compressed streams can't be seeked, so the skipped part has to be decompressed and discarded
+        io::copy(&mut reader.by_ref().take(offset), &mut io::sink())?;
+    }
+
+    Ok((reader, compression))
+}
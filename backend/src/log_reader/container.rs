@@ -0,0 +1,72 @@
+use anyhow::Result;
+use serde::Deserialize;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::log_reader::parser::LogParseError;
+use crate::log_reader::LogFormat;
+
+// A container log line with its envelope removed
+pub struct ContainerLine {
+    pub timestamp: OffsetDateTime,
+    pub stream: String,
+    pub message: String,
+    // Long lines are split into several parts, only the last one is not partial
+    pub is_partial: bool,
+}
+
+// {"log":"message\n","stream":"stdout","time":"2024-01-17T13:55:30.123456789Z"}
+#[derive(Deserialize)]
+struct DockerLine {
+    log: String,
+    stream: String,
+    #[serde(with = "time::serde::rfc3339")]
+    time: OffsetDateTime,
+}
+
+pub fn unwrap_line(line: &str, format: &LogFormat) -> Result<ContainerLine> {
+    match format {
+        LogFormat::Docker(_) => unwrap_docker_line(line),
+        _ => unwrap_cri_line(line),
+    }
+}
+
+// Docker's json-file driver, complete lines end with a newline
+fn unwrap_docker_line(line: &str) -> Result<ContainerLine> {
+    let docker_line: DockerLine = serde_json::from_str(line)
+        .map_err(|err| LogParseError::InvalidJson(format!("{} in line: {}", err, line)))?;
+
+    let mut message = docker_line.log;
+    let is_partial = !message.ends_with('\n');
+
+    if !is_partial {
+        message.truncate(message.trim_end_matches(['\n', '\r']).len());
+    }
+
+    Ok(ContainerLine {
+        timestamp: docker_line.time,
+        stream: docker_line.stream,
+        message,
+        is_partial,
+    })
+}
+
+// 2024-01-17T13:55:30.123456789Z stdout F message, where the tag is P for partial and F for full lines
+fn unwrap_cri_line(line: &str) -> Result<ContainerLine> {
+    let mut parts = line.splitn(4, ' ');
+
+    let (timestamp, stream, tag) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(timestamp), Some(stream), Some(tag)) => (timestamp, stream, tag),
+        _ => return Err(LogParseError::NoCaptureGroupsFound(line.to_string()).into()),
+    };
+
+    let timestamp = OffsetDateTime::parse(timestamp, &Rfc3339)
+        .map_err(|_| LogParseError::InvalidTimestamp(line.to_string()))?;
+
+    Ok(ContainerLine {
+        timestamp,
+        stream: stream.to_string(),
+        message: parts.next().unwrap_or("").to_string(),
+        is_partial: tag.split(':').next() == Some("P"),
+    })
+}
@@ -0,0 +1,103 @@
+use std::borrow::Cow;
+use std::io::{self, BufRead};
+
+use anyhow::{bail, Result};
+
+use crate::log_reader::parser::LogParseError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+    Windows1252,
+}
+
+impl Encoding {
+    pub fn from_label(label: &str) -> Result<Self> {
+        Ok(match label.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Encoding::Utf8,
+            "utf-16le" | "utf16le" => Encoding::Utf16Le,
+            "utf-16be" | "utf16be" => Encoding::Utf16Be,
+            "latin1" | "latin-1" | "iso-8859-1" => Encoding::Latin1,
+            "windows-1252" | "cp1252" => Encoding::Windows1252,
+            unknown_encoding => bail!("Unknown encoding: {}", unknown_encoding),
+        })
+    }
+
+    pub fn is_utf16(&self) -> bool {
+        matches!(self, Encoding::Utf16Le | Encoding::Utf16Be)
+    }
+
+    // Reads up to and including the next newline. In UTF-16 a newline is two bytes,
+    // so a single 0x0A byte only ends the line if it is part of an aligned newline code unit.
+    pub fn read_line(&self, reader: &mut impl BufRead, buf: &mut Vec<u8>) -> io::Result<usize> {
+        if !self.is_utf16() {
+            return reader.read_until(b'\n', buf);
+        }
+
+        let start = buf.len();
+
+        loop {
+            if reader.read_until(b'\n', buf)? == 0 || buf.last() != Some(&b'\n') {
+                break;
+            }
+
+            let line_len = buf.len() - start;
+            match self {
+                // The newline is 0A 00, its first byte is at an even position
+                Encoding::Utf16Le if line_len % 2 == 1 => {
+                    let mut high_byte = [0];
+                    if reader.read(&mut high_byte)? == 0 {
+                        break;
+                    }
+
+                    buf.push(high_byte[0]);
+                    if high_byte[0] == 0 {
+                        break;
+                    }
+                }
+                // The newline is 00 0A, its second byte is at an odd position
+                Encoding::Utf16Be if line_len.is_multiple_of(2) && buf[buf.len() - 2] == 0 => break,
+                _ => {}
+            }
+        }
+
+        Ok(buf.len() - start)
+    }
+
+    // Whether the line read with read_line is complete
+    pub fn ends_with_newline(&self, line: &[u8]) -> bool {
+        match self {
+            Encoding::Utf16Le => line.len().is_multiple_of(2) && line.ends_with(&[b'\n', 0]),
+            Encoding::Utf16Be => line.len().is_multiple_of(2) && line.ends_with(&[0, b'\n']),
+            _ => line.last() == Some(&b'\n'),
+        }
+    }
+
+    // Invalid byte sequences are an error, unless lossy is set in which case they are replaced with U+FFFD
+    pub fn decode<'a>(&self, bytes: &'a [u8], lossy: bool) -> Result<Cow<'a, str>, LogParseError> {
+        let decoded = match self {
+            Encoding::Utf8 if lossy => Some(String::from_utf8_lossy(bytes)),
+            Encoding::Utf8 => std::str::from_utf8(bytes).ok().map(Cow::Borrowed),
+            // Every byte is a valid Latin-1 character, with the same value as its code point
+            Encoding::Latin1 => Some(Cow::Owned(bytes.iter().map(|&byte| byte as char).collect())),
+            Encoding::Utf16Le | Encoding::Utf16Be | Encoding::Windows1252 => {
+                let encoding = match self {
+                    Encoding::Utf16Le => encoding_rs::UTF_16LE,
+                    Encoding::Utf16Be => encoding_rs::UTF_16BE,
+                    _ => encoding_rs::WINDOWS_1252,
+                };
+
+                if lossy {
+                    Some(encoding.decode_without_bom_handling(bytes).0)
+                } else {
+                    encoding.decode_without_bom_handling_and_without_replacement(bytes)
+                }
+            }
+        };
+
+        decoded.ok_or_else(|| LogParseError::InvalidEncoding(format!("{:?}", self)))
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Read};
+
+use anyhow::Result;
+use time::OffsetDateTime;
+
+use crate::listener::syslog::severity_to_level;
+use crate::log_reader::parser::LogParseError;
+use crate::LogEntry;
+
+// Binary fields larger than this are treated as a corrupted entry
+const MAX_FIELD_SIZE: u64 = 64 * 1024 * 1024;
+
+// Journal fields that are kept as additional fields, most of the others are only useful to journald itself
+const KEPT_FIELDS: [(&str, &str); 3] = [
+    ("_HOSTNAME", "hostname"),
+    ("_PID", "pid"),
+    ("_SYSTEMD_UNIT", "unit"),
+];
+
+// One JSON object per line, as written by `journalctl -o json`
+pub fn parse_json_entry(line: &str, app_i: usize) -> Result<LogEntry> {
+    let json: serde_json::Map<String, serde_json::Value> = serde_json::from_str(line)
+        .map_err(|err| LogParseError::InvalidJson(format!("{} in line: {}", err, line)))?;
+
+    let journal_fields = json
+        .into_iter()
+        .filter_map(|(key, value)| json_to_string(value).map(|value| (key, value)))
+        .collect();
+
+    build_entry(journal_fields, app_i)
+}
+
+// Strings are kept as is, binary fields are arrays of bytes and repeated fields are arrays of values
+fn json_to_string(value: serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(string) => Some(string),
+        serde_json::Value::Array(values) if values.iter().all(|value| value.is_u64()) => {
+            let bytes: Vec<u8> = values
+                .iter()
+                .filter_map(|value| value.as_u64().map(|byte| byte as u8))
+                .collect();
+            Some(String::from_utf8_lossy(&bytes).into_owned())
+        }
+        serde_json::Value::Array(values) => values.into_iter().find_map(json_to_string),
+        serde_json::Value::Null => None, // Fields that were too large to be exported
+        other => Some(other.to_string()),
+    }
+}
+
+// Reads the next entry of the journal export format, as written by `journalctl -o export`.
+// Entries are KEY=VALUE lines terminated by an empty line, binary fields are written as the key on its own line
+// followed by a little endian u64 size and the raw data. Returns None if the input ends before the entry is complete.
+pub fn read_export_entry(
+    reader: &mut impl BufRead,
+) -> io::Result<Option<(u64, BTreeMap<String, String>)>> {
+    let mut journal_fields = BTreeMap::new();
+    let mut line = Vec::new();
+    let mut bytes_read = 0;
+
+    loop {
+        line.clear();
+
+        match reader.read_until(b'\n', &mut line)? {
+            0 => return Ok(None),
+            _ if line.last() != Some(&b'\n') => return Ok(None),
+            line_len => bytes_read += line_len as u64,
+        }
+
+        let line = &line[..line.len() - 1];
+
+        if line.is_empty() {
+            if journal_fields.is_empty() {
+                continue;
+            }
+
+            return Ok(Some((bytes_read, journal_fields)));
+        }
+
+        if let Some(separator) = line.iter().position(|byte| *byte == b'=') {
+            journal_fields.insert(
+                String::from_utf8_lossy(&line[..separator]).into_owned(),
+                String::from_utf8_lossy(&line[separator + 1..]).into_owned(),
+            );
+            continue;
+        }
+
+        let mut size = [0; 8];
+        if !read_fully(reader, &mut size)? {
+            return Ok(None);
+        }
+
+        let size = u64::from_le_bytes(size);
+        if size > MAX_FIELD_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Binary journal field of {} bytes is too large", size),
+            ));
+        }
+
+        // The data is followed by a newline
+        let mut data = vec![0; size as usize + 1];
+        if !read_fully(reader, &mut data)? {
+            return Ok(None);
+        }
+        data.pop();
+
+        bytes_read += 8 + size + 1;
+        journal_fields.insert(
+            String::from_utf8_lossy(line).into_owned(),
+            String::from_utf8_lossy(&data).into_owned(),
+        );
+    }
+}
+
+// Number of complete entries, with the same record boundaries as read_export_entry
+pub fn count_export_entries(reader: &mut impl BufRead) -> io::Result<usize> {
+    let mut entries = 0;
+
+    while read_export_entry(reader)?.is_some() {
+        entries += 1;
+    }
+
+    Ok(entries)
+}
+
+// Like read_exact, but running out of input is not an error
+fn read_fully(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(_) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+pub fn build_entry(mut journal_fields: BTreeMap<String, String>, app_i: usize) -> Result<LogEntry> {
+    let message = journal_fields
+        .remove("MESSAGE")
+        .ok_or_else(|| LogParseError::InvalidMessage(format!("{:?}", journal_fields)))?; // Is required
+
+    let timestamp = match journal_fields.get("__REALTIME_TIMESTAMP") {
+        Some(timestamp) => timestamp
+            .parse::<i128>()
+            .ok()
+            .and_then(|micros| micros.checked_mul(1_000))
+            .and_then(|nanos| OffsetDateTime::from_unix_timestamp_nanos(nanos).ok())
+            .ok_or_else(|| LogParseError::InvalidTimestamp(timestamp.clone()))?,
+        None => OffsetDateTime::now_utc(),
+    };
+
+    let level = journal_fields
+        .get("PRIORITY")
+        .and_then(|priority| priority.parse::<u8>().ok())
+        .map(severity_to_level)
+        .unwrap_or(log::Level::Info);
+
+    let module = journal_fields
+        .get("SYSLOG_IDENTIFIER")
+        .or_else(|| journal_fields.get("_SYSTEMD_UNIT"))
+        .cloned()
+        .unwrap_or_else(|| "N/A".to_string());
+
+    let fields = KEPT_FIELDS
+        .iter()
+        .filter_map(|(journal_field, field)| {
+            journal_fields
+                .remove(*journal_field)
+                .map(|value| (field.to_string(), value))
+        })
+        .collect();
+
+    Ok(LogEntry {
+        timestamp,
+        level,
+        module,
+        message,
+        fields,
+        application: app_i,
+        parse_failed: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count_entries(input: &[u8]) -> io::Result<usize> {
+        count_export_entries(&mut &input[..])
+    }
+
+    fn binary_field(key: &str, data: &[u8]) -> Vec<u8> {
+        let mut field = format!("{}\n", key).into_bytes();
+        field.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        field.extend_from_slice(data);
+        field.push(b'\n');
+        field
+    }
+
+    #[test]
+    fn export_entries() {
+        let input =
+            b"\n__REALTIME_TIMESTAMP=1705499736123456\nPRIORITY=3\nSYSLOG_IDENTIFIER=sshd\n\
+            _HOSTNAME=host\n_PID=42\nMESSAGE=first = entry\n\nMESSAGE=second\n\n";
+        let mut reader = &input[..];
+
+        let (bytes_read, journal_fields) = read_export_entry(&mut reader).unwrap().unwrap();
+        assert_eq!(
+            bytes_read as usize,
+            input.len() - "MESSAGE=second\n\n".len()
+        );
+        assert_eq!(journal_fields["MESSAGE"], "first = entry");
+
+        let entry = build_entry(journal_fields, 2).unwrap();
+        assert_eq!(
+            entry.timestamp.unix_timestamp_nanos(),
+            1_705_499_736_123_456_000
+        );
+        assert_eq!(entry.level, log::Level::Error);
+        assert_eq!(entry.module, "sshd");
+        assert_eq!(entry.message, "first = entry");
+        assert_eq!(entry.application, 2);
+        assert_eq!(entry.fields["hostname"], "host");
+        assert_eq!(entry.fields["pid"], "42");
+
+        let (bytes_read, journal_fields) = read_export_entry(&mut reader).unwrap().unwrap();
+        assert_eq!(bytes_read as usize, "MESSAGE=second\n\n".len());
+        assert_eq!(journal_fields["MESSAGE"], "second");
+
+        assert!(read_export_entry(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn export_binary_field() {
+        let mut input = b"_SYSTEMD_UNIT=app.service\n".to_vec();
+        input.extend(binary_field("MESSAGE", b"two\nlines"));
+        input.extend_from_slice(b"PRIORITY=4\n\n");
+
+        let mut reader = input.as_slice();
+        let (bytes_read, journal_fields) = read_export_entry(&mut reader).unwrap().unwrap();
+        assert_eq!(bytes_read as usize, input.len());
+
+        let entry = build_entry(journal_fields, 0).unwrap();
+        assert_eq!(entry.message, "two\nlines");
+        assert_eq!(entry.module, "app.service");
+        assert_eq!(entry.level, log::Level::Warn);
+
+        // Empty lines in binary data don't end the entry
+        let mut input = binary_field("MESSAGE", b"blank\n\nline");
+        input.extend_from_slice(b"\n");
+        assert_eq!(count_entries(&input).unwrap(), 1);
+    }
+
+    #[test]
+    fn export_incomplete() {
+        let complete = binary_field("MESSAGE", b"data");
+
+        // Whatever is cut off is read again once the rest has been written
+        for len in 1..complete.len() {
+            assert_eq!(count_entries(&complete[..len]).unwrap(), 0, "{}", len);
+        }
+
+        assert_eq!(count_entries(b"MESSAGE=hi\n").unwrap(), 0);
+        assert_eq!(count_entries(b"MESSAGE=hi").unwrap(), 0);
+        assert_eq!(count_entries(b"\n\n\n").unwrap(), 0);
+        assert_eq!(count_entries(b"MESSAGE=hi\n\nMESSAGE=there\n").unwrap(), 1);
+    }
+
+    #[test]
+    fn export_binary_field_too_large() {
+        let mut input = b"MESSAGE\n".to_vec();
+        input.extend_from_slice(&u64::MAX.to_le_bytes());
+        input.extend_from_slice(b"data\n\n");
+
+        let err = read_export_entry(&mut input.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn invalid_entries() {
+        let journal_fields = |fields: &[(&str, &str)]| {
+            fields
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let error = |fields: &[(&str, &str)]| {
+            build_entry(journal_fields(fields), 0)
+                .unwrap_err()
+                .downcast::<LogParseError>()
+                .unwrap()
+        };
+
+        assert!(matches!(
+            error(&[("PRIORITY", "3")]),
+            LogParseError::InvalidMessage(_)
+        ));
+        for timestamp in [
+            "yesterday",
+            "-",
+            "1e9",
+            "170549973612345678901234567890123456",
+        ] {
+            assert!(matches!(
+                error(&[("MESSAGE", "hi"), ("__REALTIME_TIMESTAMP", timestamp)]),
+                LogParseError::InvalidTimestamp(_)
+            ));
+        }
+
+        // Unknown priorities don't make the entry invalid
+        let entry = build_entry(
+            journal_fields(&[("MESSAGE", "hi"), ("PRIORITY", "high")]),
+            0,
+        )
+        .unwrap();
+        assert_eq!(entry.level, log::Level::Info);
+        assert_eq!(entry.module, "N/A");
+    }
+
+    #[test]
+    fn json_entries() {
+        let entry = parse_json_entry(
+            r#"{"__REALTIME_TIMESTAMP":"1705499736123456","PRIORITY":"6","SYSLOG_IDENTIFIER":["first","second"],"MESSAGE":[104,105],"_PID":42,"_HOSTNAME":null}"#,
+            1,
+        )
+        .unwrap();
+
+        assert_eq!(entry.message, "hi");
+        assert_eq!(entry.module, "first");
+        assert_eq!(entry.level, log::Level::Info);
+        assert_eq!(entry.fields["pid"], "42");
+        assert!(!entry.fields.contains_key("hostname"));
+
+        for line in ["", "not json", "[1, 2]", r#"{"MESSAGE": "unterminated"#] {
+            assert!(matches!(
+                parse_json_entry(line, 0)
+                    .unwrap_err()
+                    .downcast_ref::<LogParseError>(),
+                Some(LogParseError::InvalidJson(_))
+            ));
+        }
+        assert!(matches!(
+            parse_json_entry(r#"{"MESSAGE":null}"#, 0)
+                .unwrap_err()
+                .downcast_ref::<LogParseError>(),
+            Some(LogParseError::InvalidMessage(_))
+        ));
+    }
+}
@@ -1,331 +1,1272 @@
-use config::{Value, ValueKind};
+use anyhow::{anyhow, bail, Result};
 use glob::glob;
 use log::{debug, error, trace, warn};
 use regex::Regex;
-use ringbuffer::{AllocRingBuffer, RingBuffer};
+use serde::{Deserialize, Serialize};
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
-use std::fs::metadata;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, metadata, Metadata};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{BufRead, Read};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use sysinfo::System;
-use time::format_description::{self, FormatItem};
+use time::format_description::OwnedFormatItem;
+use time::OffsetDateTime;
 use tokio::sync::{Mutex, RwLock};
 
+use crate::log_buffer::store::{self, STORE_PATH};
+use crate::log_buffer::{enforce_max_memory, LogBuffer, MAX_MEMORY};
+use crate::log_reader::application::Application;
+use crate::log_reader::compression::Compression;
+use crate::log_reader::stats::ParseStats;
 use crate::LogEntry;
-use crate::SETTINGS;
 
+pub mod application;
+mod compression;
+mod container;
+mod encoding;
+pub mod journal;
 mod parser;
+pub mod parser_test;
+mod presets;
+pub mod stats;
+pub mod watcher;
 
-pub enum TimeFormat<'a> {
+// Used to estimate the memory required by buffers that are only limited by their number of entries
+const AVERAGE_MESSAGE_SIZE: u64 = 100;
+
+// Number of bytes at the start of a file that its fingerprint is taken from
+const FINGERPRINT_SIZE: u64 = 1024;
+
+// Container runtimes split long lines into parts, a message that grows beyond this is added without its final part
+const MAX_PARTIAL_MESSAGE_SIZE: usize = 1024 * 1024;
+
+pub enum TimeFormat {
     Iso8601,
     Rfc3339,
     Rfc2822,
-    Custom(Vec<FormatItem<'a>>),
+    EpochS,
+    EpochMs,
+    EpochNs,
+    Custom(OwnedFormatItem),
+}
+
+pub enum LogFormat {
+    Regex(Regex),
+    Json(FieldNames),
+    Logfmt(FieldNames),
+    JournalJson,
+    JournalExport,
+    Docker(Option<Regex>),
+    Cri(Option<Regex>),
+}
+
+// Names of the JSON or logfmt fields that are mapped to the log entry
+pub struct FieldNames {
+    pub timestamp: String,
+    pub level: String,
+    pub module: String,
+    pub message: String,
+}
+
+// Decides which lines continue the previous log entry instead of starting a new one
+pub enum Multiline {
+    Unmatched,
+    Continuation(Regex),
+}
+
+// What happens to lines that can't be parsed
+pub enum ParseErrorPolicy {
+    Drop,
+    KeepRaw,
+    AppendToPrevious,
+}
+
+impl ParseErrorPolicy {
+    pub fn from_label(label: &str) -> Result<Self> {
+        match label {
+            "drop" => Ok(ParseErrorPolicy::Drop),
+            "keep_raw" => Ok(ParseErrorPolicy::KeepRaw),
+            "append_to_previous" => Ok(ParseErrorPolicy::AppendToPrevious),
+            unknown_policy => bail!("Unknown on_parse_error policy: {}", unknown_policy),
+        }
+    }
+}
+
+// Files are identified by device and inode, so that rotated (renamed) files are still recognized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FileId {
+    device: u64,
+    inode: u64,
+}
+
+// How far a file has been read, in bytes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileState {
+    path: PathBuf,
+    offset: u64,
+    #[serde(default)]
+    fingerprint: Option<Fingerprint>,
+    // Set once the file has been read from
+    #[serde(default)]
+    source: Option<LineSource>,
+}
+
+// A file or ingest stream that lines are read from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LineSource {
+    // Continuation lines are appended to the source's last entry, which the log buffer keeps track of by this id
+    id: u64,
+    // Partial container messages by stream, until their final part arrives
+    #[serde(skip)]
+    partial_messages: HashMap<String, String>,
+    #[serde(default)]
+    held_back: Option<HeldBack>,
+}
+
+// Set when reading stopped at a partial container message, which is read again together with its final part.
+// Lines of the other streams were already read up to end, those of the streams in partial_starts up to
+// where their partial message starts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct HeldBack {
+    offset: u64,
+    end: u64,
+    partial_starts: HashMap<String, u64>,
 }
 
+// Hash of the first bytes of a file, used to recognize copies of it (logrotate's copytruncate) and
+// files whose contents were replaced without changing their inode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct Fingerprint {
+    len: u64,
+    hash: u64,
+}
+
+struct LogFile {
+    path: PathBuf,
+    id: FileId,
+    len: u64,
+    modified: SystemTime,
+}
+
+// Reads the new log entries of the applications. Without changed paths, all the files of the applications are looked at,
+// otherwise only those that the watcher's events named.
+#[allow(clippy::too_many_arguments)]
 pub async fn load_logs(
-    buffer: Arc<RwLock<HashMap<usize, AllocRingBuffer<LogEntry>>>>,
-    cache: Arc<Mutex<HashMap<String, (std::time::SystemTime, usize)>>>,
+    buffer: Arc<RwLock<HashMap<usize, LogBuffer>>>,
+    cache: Arc<Mutex<HashMap<FileId, FileState>>>,
     i_to_app: Arc<Mutex<HashMap<usize, String>>>,
+    parse_stats: Arc<Mutex<HashMap<usize, BTreeMap<String, ParseStats>>>>,
+    applications: &[Arc<Application>],
+    changed_paths: Option<&HashSet<PathBuf>>,
     sysinfo: Arc<Mutex<System>>,
     is_init: bool,
 ) {
+    // Same locking order as the request handlers, which hold i_to_app while reading the buffer
+    let mut i_to_app = i_to_app.lock().await;
     let mut log_buffer_map = buffer.write().await;
     let mut log_files = Vec::new();
     let mut cache = cache.lock().await;
-    let mut i_to_app = i_to_app.lock().await;
-    let mut app_i;
-
-    let apps = SETTINGS
-        .get_array("application")
-        .unwrap_or_else(|_| vec![Value::new(None, create_default_map())]);
-
-    for app in apps {
-        let app_table = app
-            .into_table()
-            .expect("Config file is formatted incorrectly!");
-
-        let app_path = app_table
-            .get("path")
-            .expect("An application is missing the required path in the config!")
-            .clone()
-            .into_string()
-            .expect("Path is not a string!");
-
-        let app_name = app_table
-            .get("name")
-            .map(|name| name.clone().into_string().expect("Name is not a string!"))
-            .unwrap_or_else(|| app_path.clone());
-
-        let level_map: Option<HashMap<String, String>> = app_table
-            .get("level_map")
-            .and_then(|level_map| level_map.clone().into_table().ok())
-            .map(|table| {
-                table
-                    .into_iter()
-                    .filter_map(|(k, v)| v.into_string().ok().map(|val| (k, val)))
-                    .collect()
-            });
+    let mut parse_stats = parse_stats.lock().await;
+    let mut seen_files = HashSet::new();
+    // Files that were in these paths before but weren't seen now are forgotten, None if all paths were looked at
+    let mut scanned_paths = changed_paths.map(|_| Vec::new());
 
-        if !i_to_app
-            .values()
-            .any(|stored_app_name| stored_app_name == &app_name)
-        {
-            let length = i_to_app.len();
-            app_i = length;
-            i_to_app.insert(length, app_name.clone());
-        } else {
-            app_i = *i_to_app
-                .iter()
-                .find(|(_, stored_app_name)| stored_app_name == &&app_name)
-                .unwrap()
-                .0;
-        }
-
-        let app_parser = Regex::new(
-            &app_table
-                .get("parser")
-                .expect("An application is missing the parser field in the config!")
-                .clone()
-                .into_string()
-                .expect("Parser is not a string!"),
-        )
-        .expect("Failed to compile regex!");
+    for app in applications {
+        let app_i = get_app_index(&mut i_to_app, &app.name);
+        let mut app_changed_paths = changed_paths;
 
-        let configured_timeformat = if let Some(configured_timeformat) = app_table.get("timeformat")
-        {
-            configured_timeformat.clone().to_string()
-        } else {
-            "iso8601".to_string()
+        let log_buffer = match log_buffer_map.entry(app_i) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => match create_buffer(
+                app.buffer_size,
+                app.buffer_bytes,
+                app.retention,
+                &app.name,
+                app_i,
+                &mut *sysinfo.lock().await,
+            ) {
+                Ok(mut log_buffer) => {
+                    // The stored entries were read up to these states, which can be newer than the saved ones
+                    for read_state in log_buffer.take_read_states() {
+                        match serde_json::from_str::<Vec<(FileId, FileState)>>(&read_state) {
+                            Ok(file_states) => cache.extend(file_states),
+                            Err(err) => error!("Failed to parse a stored read state! {}", err),
+                        }
+                    }
+
+                    // All the files of new applications are read
+                    app_changed_paths = None;
+                    entry.insert(log_buffer)
+                }
+                Err(err) if is_init => panic!("{}", err),
+                Err(err) => {
+                    // Applications of templates can appear at any time, the others keep being updated
+                    error!("Skipping application {}! {}", app.name, err);
+                    continue;
+                }
+            },
+        };
+
+        let app_path = match &app.path {
+            Some(app_path) => app_path,
+            None => continue,
         };
 
-        let app_timeformat = match configured_timeformat.as_str() {
-            "iso8601" => TimeFormat::Iso8601,
-            "rfc3339" => TimeFormat::Rfc3339,
-            "rfc2822" => TimeFormat::Rfc2822,
-            custom_format_str => {
-                let format_desc = format_description::parse_borrowed::<1>(custom_format_str)
-                    .expect("Invalid custom time format!");
-                TimeFormat::Custom(format_desc)
+        let app_scanned_paths = match app_changed_paths {
+            Some(app_changed_paths) => {
+                let app_scanned_paths = get_changed_app_paths(app_path, app_changed_paths);
+                if app_scanned_paths.is_empty() {
+                    continue;
+                }
+
+                app_scanned_paths
             }
+            None => vec![PathBuf::from(app_path)],
         };
 
         debug!("Loading logs for application: {}", app_path);
 
-        if metadata(&app_path)
-            .expect("Failed to read metadata for log file")
-            .is_file()
-        {
-            log_files.push((
-                PathBuf::from(app_path.clone()),
-                get_modified_time(&app_path),
-            ));
-        } else {
-            for log_file in
-                glob(format!("{}/**/*", &app_path).as_str()).expect("Failed to read glob pattern")
-            {
-                if let Ok(path) = log_file {
-                    if let Ok(metadata) = metadata(&path) {
-                        if metadata.is_file() {
-                            log_files
-                                .push((path.clone(), get_modified_time(path.to_str().unwrap())));
-                        }
-                    } else {
-                        error!("Failed to read log file metadata! {:?}", path);
-                    }
-                } else {
-                    error!("Failed to read log file! {:?}", log_file);
-                }
-            }
+        for scanned_path in &app_scanned_paths {
+            find_log_files(app, app_path, scanned_path, &mut log_files);
         }
 
-        let log_buffer = match log_buffer_map.entry(app_i) {
-            Entry::Occupied(entry) => entry.into_mut(),
-            Entry::Vacant(entry) => {
-                let app_buffer_size = app_table
-                    .get("buffer_size")
-                    .unwrap_or(&Value::new(None, ValueKind::U64(1_000_000)))
-                    .clone()
-                    .into_uint()
-                    .expect("buffer_size is not parsable to an unsigned integer!");
-
-                // This memory check is expensive, but it is only done once during initialization
-                let memory_required = get_memory_required::<LogEntry>(app_buffer_size);
-                let mut sys = sysinfo.lock().await;
-                let available_memory = get_available_memory(&mut sys);
-
-                if available_memory < memory_required {
-                    panic!("{} MB is required to allocate buffer for {}, which is more than the currently available memory of {} MB!",
-                        memory_required / 1_048_576, &app_path, available_memory / 1_048_576);
-                } else if available_memory < memory_required * 2 {
-                    warn!("{} MB is required to allocate buffer for {}, which is more than half of the currently available memory! \
-                    Consider reducing the buffer sizes to avoid runtime out-of-memory issues.", memory_required / 1_048_576, &app_path);
-                } else {
-                    debug!(
-                        "{} MB is required for application {}",
-                        memory_required / 1_048_576,
-                        &app_path
-                    );
-                }
+        if let Some(scanned_paths) = &mut scanned_paths {
+            scanned_paths.extend(app_scanned_paths);
+        }
 
-                entry.insert(AllocRingBuffer::new(app_buffer_size as usize))
+        // A file can be named by several events, or also be in a directory that was named
+        let mut found_files = HashSet::new();
+        log_files.retain(|log_file| found_files.insert(log_file.id));
+
+        log_files.sort_by_key(|file| std::cmp::Reverse(file.modified)); // Newest files first
+        let pushed = log_buffer.pushed();
+        let mut read_states = Vec::new();
+
+        // Copies of files that have already been read continue where the original was left off.
+        // This also applies to files that were overwritten with a copy.
+        for log_file in log_files.iter() {
+            if cache
+                .get(&log_file.id)
+                .is_some_and(|state| !state.is_truncated(log_file))
+            {
+                continue;
             }
-        };
 
-        log_files.sort_by(|a, b| b.1.cmp(&a.1)); // Newest files first
+            if let Some(original) = find_original(&cache, log_file) {
+                debug!(
+                    "Log file {:?} is a copy of {:?}, reading from offset {}",
+                    log_file.path, original.path, original.offset
+                );
+                cache.insert(
+                    log_file.id,
+                    FileState {
+                        path: log_file.path.clone(),
+                        offset: original.offset.min(log_file.len),
+                        fingerprint: original.fingerprint,
+                        source: original.source,
+                    },
+                );
+            }
+        }
+
+        // Files whose state was restored from the store continue where they were left off
+        let mut restored_files = Vec::new();
 
         if is_init {
             // During first load we need to first exclude files that would fall outside the buffer.
-            // These files will be inserted into the cache and not processed.
+            // These files will be inserted into the cache as fully read and not processed.
+
+            // Files last written before the retention window only contain expired entries. A window reaching
+            // back further than the clock can go keeps all files.
+            if let Some(window_start) = log_buffer
+                .retention()
+                .and_then(|retention| SystemTime::now().checked_sub(retention))
+            {
+                let expired_files: HashSet<FileId> = log_files
+                    .iter()
+                    .filter(|log_file| log_file.modified < window_start)
+                    .map(|log_file| {
+                        trace!("Skipping expired log file {:?}", log_file.path);
+                        cache.insert(log_file.id, FileState::new(log_file, log_file.len));
+                        log_file.id
+                    })
+                    .collect();
+
+                seen_files.extend(expired_files.iter().copied());
+                log_files.retain(|log_file| !expired_files.contains(&log_file.id));
+            }
 
-            let mut total_line_count: usize = 0;
+            (restored_files, log_files) = log_files
+                .into_iter()
+                .partition(|log_file| cache.contains_key(&log_file.id));
+
+            let mut total_entry_count: usize = 0;
             let buffer_size = log_buffer.capacity();
             let mut file_iterator = log_files.iter(); // Starting from the newest file
 
-            while total_line_count < buffer_size {
+            // Parsed entries take up about as much memory as the lines they were parsed from
+            let mut total_bytes: u64 = 0;
+            let buffer_bytes = log_buffer
+                .max_bytes()
+                .into_iter()
+                .chain(*MAX_MEMORY)
+                .min()
+                .unwrap_or(usize::MAX) as u64;
+
+            while total_entry_count < buffer_size && total_bytes < buffer_bytes {
                 let log_file = match file_iterator.next() {
                     Some(file) => file,
                     None => break,
                 };
 
-                debug!(
-                    "Counting lines in log file: {}",
-                    log_file.0.to_str().unwrap()
-                );
+                total_bytes += log_file.len;
+                if total_bytes >= buffer_bytes {
+                    // Read as a whole, the oldest entries are evicted once they no longer fit
+                    break;
+                }
+
+                debug!("Counting entries in log file: {:?}", log_file.path);
+
+                let file_entry_count = count_entries(&log_file.path, app);
+                if total_entry_count + file_entry_count >= buffer_size {
+                    // This is the earliest file we need to read from, skip the entries that don't fit
+                    let entries_to_skip = file_entry_count - (buffer_size - total_entry_count);
 
-                let file_line_count =
-                    BufReader::new(File::open(log_file.0.clone()).expect("Failed to open file"))
-                        .lines()
-                        .count();
-                if total_line_count + file_line_count >= buffer_size {
-                    // This is the earliest file we need to read from
-                    // Bump the cache time so that the following retain call will include it
                     cache.insert(
-                        log_file.0.to_str().unwrap().to_string(),
-                        (
-                            log_file.1.checked_sub(Duration::from_secs(1)).unwrap(),
-                            file_line_count - (buffer_size - total_line_count),
+                        log_file.id,
+                        FileState::new(
+                            log_file,
+                            get_entry_offset(&log_file.path, entries_to_skip, app),
                         ),
                     );
                     break;
                 } else {
-                    total_line_count += file_line_count;
+                    total_entry_count += file_entry_count;
                 }
             }
 
             // Add the rest to cache
-            for log_file in file_iterator {
-                cache.insert(log_file.0.to_str().unwrap().to_string(), (log_file.1, 0));
-            }
+            let skipped_files: HashSet<FileId> = file_iterator
+                .map(|log_file| {
+                    cache.insert(log_file.id, FileState::new(log_file, log_file.len));
+                    log_file.id
+                })
+                .collect();
+
+            seen_files.extend(skipped_files.iter().copied());
+            log_files.retain(|log_file| !skipped_files.contains(&log_file.id));
         }
 
-        // Filter out files that haven't been modified since the last time we read them
-        log_files.retain(|file| {
-            let (path, modified_time) = file;
-            match cache.get(path.to_str().unwrap()) {
-                Some((cache_time, _)) => modified_time > cache_time,
-                None => true,
-            }
-        });
+        let restored_ids: HashSet<FileId> =
+            restored_files.iter().map(|log_file| log_file.id).collect();
+        log_files.append(&mut restored_files);
+        log_files.sort_by_key(|file| std::cmp::Reverse(file.modified));
 
         for log_file in log_files.iter().rev() {
-            debug!("Reading log file: {}", log_file.0.to_str().unwrap());
-            let file = File::open(log_file.0.clone()).expect("Failed to open file");
-            let reader = BufReader::new(file);
-            let mut line_count = 0;
+            let offset = match cache.get_mut(&log_file.id) {
+                // Set above while deciding what fits into the buffer
+                Some(state) if is_init && !restored_ids.contains(&log_file.id) => state.offset,
+                Some(state) if state.is_truncated(log_file) => {
+                    // Most likely truncated in place by logrotate's copytruncate
+                    debug!(
+                        "Log file {:?} was truncated, reading from the start",
+                        log_file.path
+                    );
+                    0
+                }
+                Some(state) if log_file.len == state.offset => {
+                    if state.path != log_file.path {
+                        // Renamed during rotation, everything in it has already been read
+                        debug!(
+                            "Log file {:?} was renamed to {:?}",
+                            state.path, log_file.path
+                        );
+                        state.path = log_file.path.clone();
+                    }
 
-            let lines_to_skip = match cache.get(log_file.0.to_str().unwrap()) {
-                Some(cached_value) => cached_value.1,
+                    continue;
+                }
+                Some(state) => state.offset,
                 None => 0,
             };
 
-            for (i, line) in reader.lines().skip(lines_to_skip).enumerate() {
-                match line {
-                    Ok(line) => {
-                        match parser::parse_entry(
-                            &line,
-                            &app_parser,
-                            &app_timeformat,
-                            app_i,
-                            &level_map,
-                        ) {
-                            Ok(parse_result) => {
-                                trace!("{:?}", parse_result);
-                                log_buffer.push(parse_result);
-                            }
-                            Err(err) => {
-                                error!(
-                                    "{} on line {} in file {}",
-                                    err,
-                                    i + 1,
-                                    log_file.0.to_str().unwrap()
-                                );
-                            }
-                        }
+            debug!(
+                "Reading log file {:?} from offset {}",
+                log_file.path, offset
+            );
+
+            let (mut reader, compression) = match compression::open(&log_file.path, offset) {
+                Ok(opened) => opened,
+                Err(err) => {
+                    error!("Failed to open log file {:?}! {}", log_file.path, err);
+                    continue;
+                }
+            };
+            let is_compressed = compression != Compression::None;
+
+            if is_compressed && !is_init && !cache.contains_key(&log_file.id) {
+                // Compressed files showing up later are rotated copies of files that have already been read
+                debug!(
+                    "Skipping {:?} compressed log file {:?}",
+                    compression, log_file.path
+                );
+                cache.insert(log_file.id, FileState::new(log_file, log_file.len));
+                continue;
+            }
+
+            let mut source = cache
+                .get_mut(&log_file.id)
+                .and_then(|state| state.source.take())
+                .unwrap_or_else(|| LineSource::new(log_file.id.source_id()));
+
+            let (offset, file_stats) = if matches!(app.format, LogFormat::JournalExport) {
+                read_journal_export(&mut reader, offset, &log_file.path, app_i, log_buffer)
+            } else {
+                read_lines(
+                    &mut reader,
+                    offset,
+                    &log_file.path,
+                    is_compressed,
+                    app,
+                    &mut source,
+                    log_buffer,
+                )
+            };
+
+            if !file_stats.is_empty() {
+                parse_stats
+                    .entry(app_i)
+                    .or_default()
+                    .entry(log_file.path.display().to_string())
+                    .or_default()
+                    .merge(file_stats);
+            }
+
+            // Compressed files are not appended to, their offset only marks them as read
+            let mut state =
+                FileState::new(log_file, if is_compressed { log_file.len } else { offset });
+            state.source = Some(source);
+            read_states.push((log_file.id, state.clone()));
+            cache.insert(log_file.id, state);
+        }
+
+        seen_files.extend(log_files.drain(..).map(|log_file| log_file.id));
+
+        // Stored along with the new entries, so that they aren't read again if files.json isn't saved afterwards
+        let read_state = serde_json::to_string(&read_states).unwrap_or_default();
+        log_buffer.commit_read_state((log_buffer.pushed() - pushed) as usize, &read_state);
+        enforce_max_memory(&mut log_buffer_map);
+    }
+
+    // Forget files that no longer exist, their inodes may get reused by new files
+    cache.retain(|id, state| {
+        seen_files.contains(id)
+            || scanned_paths.as_ref().is_some_and(|scanned_paths| {
+                !scanned_paths
+                    .iter()
+                    .any(|scanned_path| state.path.starts_with(scanned_path))
+            })
+    });
+
+    // Along with their parse errors, the errors of ingested and received lines are kept
+    let seen_paths: HashSet<String> = cache
+        .values()
+        .map(|state| state.path.display().to_string())
+        .collect();
+    for app_stats in parse_stats.values_mut() {
+        app_stats.retain(|path, _| {
+            matches!(path.as_str(), "ingest" | "syslog") || seen_paths.contains(path)
+        });
+    }
+
+    save_file_states(&cache);
+}
+
+// The changed paths that are within the application's path. Event paths start with the canonical path that is watched,
+// so they are translated back to the configured path, which the paths in the cache are based on.
+fn get_changed_app_paths(app_path: &str, changed_paths: &HashSet<PathBuf>) -> Vec<PathBuf> {
+    let canonical_app_path = match fs::canonicalize(app_path) {
+        Ok(canonical_app_path) => canonical_app_path,
+        Err(_) => return Vec::new(),
+    };
+
+    changed_paths
+        .iter()
+        .filter_map(|changed_path| changed_path.strip_prefix(&canonical_app_path).ok())
+        .map(|relative_path| match relative_path.as_os_str().is_empty() {
+            true => PathBuf::from(app_path),
+            false => Path::new(app_path).join(relative_path),
+        })
+        .collect()
+}
+
+// Adds the log files at the path, which is either a single file or a directory that is searched recursively
+fn find_log_files(app: &Application, app_path: &str, path: &Path, log_files: &mut Vec<LogFile>) {
+    let is_file = match metadata(path) {
+        Ok(metadata) => metadata.is_file(),
+        Err(err) => {
+            // Directories of templated applications and removed files can disappear at any time
+            if path == Path::new(app_path) {
+                error!("Failed to read metadata for {}! {}", app_path, err);
+            }
+            return;
+        }
+    };
+
+    if is_file {
+        let relative_path = path.strip_prefix(app_path).unwrap_or(path);
+
+        // Only the files in the application's directory are filtered, not the application's path itself
+        if relative_path.as_os_str().is_empty() || app.is_included(relative_path) {
+            log_files.extend(LogFile::new(path.to_path_buf()));
+        } else {
+            trace!("Skipping excluded file {:?}", path);
+        }
+        return;
+    }
+
+    for log_file in
+        glob(format!("{}/**/*", path.display()).as_str()).expect("Failed to read glob pattern")
+    {
+        if let Ok(path) = log_file {
+            let relative_path = path.strip_prefix(app_path).unwrap_or(&path);
+
+            if !app.is_included(relative_path) {
+                trace!("Skipping excluded file {:?}", path);
+                continue;
+            }
+
+            if let Some(log_file) = LogFile::new(path) {
+                log_files.push(log_file);
+            }
+        } else {
+            error!("Failed to read log file! {:?}", log_file);
+        }
+    }
+}
+
+// The read offsets are kept next to the stored entries, so that only what was written in the meantime is read
+pub fn load_file_states() -> HashMap<FileId, FileState> {
+    let path = match &*STORE_PATH {
+        Some(store_path) => store_path.join("files.json"),
+        None => return HashMap::new(),
+    };
+
+    let file_states: Vec<(FileId, FileState)> = match fs::read(&path) {
+        Ok(file_states) => serde_json::from_slice(&file_states).unwrap_or_else(|err| {
+            error!(
+                "Failed to parse {:?}, all log files are read again! {}",
+                path, err
+            );
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    };
+
+    file_states.into_iter().collect()
+}
+
+// Written to a temporary file first, so that a crash doesn't leave a partially written file behind
+fn save_file_states(cache: &HashMap<FileId, FileState>) {
+    let store_path = match &*STORE_PATH {
+        Some(store_path) => store_path,
+        None => return,
+    };
+
+    let path = store_path.join("files.json");
+    let tmp_path = store_path.join("files.json.tmp");
+    let file_states: Vec<(&FileId, &FileState)> = cache.iter().collect();
+
+    let result = fs::create_dir_all(store_path)
+        .and_then(|_| fs::write(&tmp_path, serde_json::to_vec(&file_states)?))
+        .and_then(|_| fs::rename(&tmp_path, &path));
+
+    if let Err(err) = result {
+        error!("Failed to save the read offsets to {:?}! {}", path, err);
+    }
+}
+
+// Reads complete lines from the given offset, returns the offset after the last complete line
+// and the parse errors that were encountered
+fn read_lines(
+    reader: &mut impl BufRead,
+    offset: u64,
+    path: &Path,
+    is_compressed: bool,
+    app: &Application,
+    source: &mut LineSource,
+    log_buffer: &mut LogBuffer,
+) -> (u64, ParseStats) {
+    let app_i = log_buffer.app_i();
+    let mut line_buffer = Vec::new();
+    let mut stats = ParseStats::default();
+    let mut offset = offset;
+    // Only applies if reading continues where it was held back, the file may have been truncated meanwhile
+    let held_back = source
+        .held_back
+        .take()
+        .filter(|held_back| held_back.offset == offset);
+    let mut partial_starts = HashMap::new();
+
+    loop {
+        line_buffer.clear();
+
+        let line_start = offset;
+        match app.encoding.read_line(reader, &mut line_buffer) {
+            Ok(0) => break,
+            Ok(_) if !is_compressed && !app.encoding.ends_with_newline(&line_buffer) => {
+                // The line is still being written, it will be read once it is complete
+                trace!(
+                    "Holding back partial line at offset {} in file {:?}",
+                    line_start,
+                    path
+                );
+                break;
+            }
+            Ok(bytes_read) => offset += bytes_read as u64,
+            Err(err) => {
+                error!("Failed to read log file {:?}! {}", path, err);
+                break;
+            }
+        }
+
+        match app.encoding.decode(&line_buffer, app.lossy) {
+            Ok(line) => {
+                let mut line = line.trim_end_matches(['\n', '\r']);
+
+                if line_start == 0 {
+                    // The byte order mark at the start of the file is not part of the first line
+                    line = line.trim_start_matches('\u{feff}');
+                }
+
+                if held_back
+                    .as_ref()
+                    .is_some_and(|held_back| held_back.was_read(line, line_start, app))
+                {
+                    continue;
+                }
+
+                if let Err(err) = process_line(line, app, app_i, source, log_buffer, &mut stats) {
+                    error!("{} at offset {} in file {:?}", err, line_start, path);
+                }
+
+                // Keeps track of where the partial message of each stream starts
+                partial_starts.retain(|stream, _| source.partial_messages.contains_key(stream));
+                for stream in source.partial_messages.keys() {
+                    if !partial_starts.contains_key(stream) {
+                        partial_starts.insert(stream.clone(), line_start);
+                    }
+                }
+            }
+            Err(err) => {
+                error!("{} at offset {} in file {:?}", err, line_start, path);
+                stats.record_error(&err.into(), &String::from_utf8_lossy(&line_buffer));
+            }
+        }
+    }
+
+    source.partial_messages.clear();
+
+    if let Some(partial_start) = partial_starts.values().min().copied() {
+        if !is_compressed {
+            // The final part of a split container log line hasn't been written yet, so the parts are read again later
+            trace!(
+                "Holding back partial container line at offset {} in file {:?}",
+                partial_start,
+                path
+            );
+            source.held_back = Some(HeldBack {
+                offset: partial_start,
+                end: offset,
+                partial_starts,
+            });
+            return (partial_start, stats);
+        }
+    }
+
+    (offset, stats)
+}
+
+// Journal export entries span multiple lines, so they are read entry by entry instead
+fn read_journal_export(
+    reader: &mut impl BufRead,
+    offset: u64,
+    path: &Path,
+    app_i: usize,
+    log_buffer: &mut LogBuffer,
+) -> (u64, ParseStats) {
+    let mut offset = offset;
+    let mut stats = ParseStats::default();
+
+    loop {
+        let entry_start = offset;
+        match journal::read_export_entry(reader) {
+            Ok(Some((bytes_read, journal_fields))) => {
+                offset += bytes_read;
+
+                match journal::build_entry(journal_fields, app_i) {
+                    Ok(entry) => {
+                        trace!("{:?}", entry);
+                        log_buffer.push(entry);
                     }
                     Err(err) => {
-                        error!("{}", err);
+                        error!("{} at offset {} in file {:?}", err, entry_start, path);
+                        stats.record_error(&err, "");
                     }
                 }
+            }
+            Ok(None) => {
+                // The entry is still being written, it will be read once it is complete
+                trace!(
+                    "Holding back partial entry at offset {} in file {:?}",
+                    entry_start,
+                    path
+                );
+                break;
+            }
+            Err(err) => {
+                error!("Failed to read log file {:?}! {}", path, err);
+                break;
+            }
+        }
+    }
 
-                line_count = i;
+    (offset, stats)
+}
+
+// Parses a line into the application's buffer, continuation lines are appended to the previous entry instead.
+// Partial container log lines are collected in partial_messages until their final part arrives.
+// Lines that couldn't be parsed are recorded in stats, also when they are kept.
+pub fn process_line(
+    line: &str,
+    app: &Application,
+    app_i: usize,
+    source: &mut LineSource,
+    log_buffer: &mut LogBuffer,
+    stats: &mut ParseStats,
+) -> Result<()> {
+    let container_line = match app.format {
+        LogFormat::Docker(_) | LogFormat::Cri(_) => {
+            let mut container_line = match container::unwrap_line(line, &app.format) {
+                Ok(container_line) => container_line,
+                Err(err) => {
+                    return handle_parse_error(
+                        err,
+                        line,
+                        &app.on_parse_error,
+                        app.multiline_max_size,
+                        source,
+                        log_buffer,
+                        stats,
+                    )
+                }
+            };
+
+            // stdout and stderr are written to the same file, so their parts can be interleaved
+            if let Some(mut partial_message) =
+                source.partial_messages.remove(&container_line.stream)
+            {
+                partial_message.push_str(&container_line.message);
+                container_line.message = partial_message;
             }
 
-            cache.insert(
-                log_file.0.to_str().unwrap().to_string(),
-                (log_file.1, line_count),
-            );
+            if container_line.is_partial {
+                if container_line.message.len() < MAX_PARTIAL_MESSAGE_SIZE {
+                    source
+                        .partial_messages
+                        .insert(container_line.stream, container_line.message);
+                    return Ok(());
+                }
+
+                // The final part may never arrive, so the message is added as it is
+                trace!(
+                    "Partial container message on {} is too large, adding it as is",
+                    container_line.stream
+                );
+            }
+
+            Some(container_line)
         }
+        _ => None,
+    };
 
-        log_files.clear();
+    let line = container_line
+        .as_ref()
+        .map_or(line, |container_line| container_line.message.as_str());
+
+    if let Some(Multiline::Continuation(continuation_re)) = &app.multiline {
+        if continuation_re.is_match(line) {
+            append_continuation(log_buffer, source, line, app.multiline_max_size);
+            return Ok(());
+        }
+    }
+
+    let parse_result = match &container_line {
+        Some(container_line) => parser::parse_container_entry(container_line, app, app_i, stats),
+        None => parser::parse_entry(line, app, app_i, stats),
+    };
+
+    match parse_result {
+        Ok(parse_result) => {
+            trace!("{:?}", parse_result);
+            log_buffer.push_from(source.id, parse_result);
+            Ok(())
+        }
+        // Any line that doesn't parse continues the previous entry, if there is one
+        Err(_)
+            if matches!(app.multiline, Some(Multiline::Unmatched))
+                && log_buffer.last_entry(source.id).is_some() =>
+        {
+            append_continuation(log_buffer, source, line, app.multiline_max_size);
+            Ok(())
+        }
+        Err(err) => handle_parse_error(
+            err,
+            line,
+            &app.on_parse_error,
+            app.multiline_max_size,
+            source,
+            log_buffer,
+            stats,
+        ),
     }
 }
 
-fn get_modified_time(path: &str) -> std::time::SystemTime {
-    match metadata(path) {
-        Ok(metadata) => metadata.modified().unwrap_or_else(|_| {
-            error!("Failed to read modified time for log file!");
-            std::time::SystemTime::UNIX_EPOCH
-        }),
-        Err(e) => {
-            error!("Failed to read metadata for log file! {:?}", e);
-            std::time::SystemTime::UNIX_EPOCH
+// Applies the on_parse_error policy of an application or listener, dropped lines are returned as errors
+pub fn handle_parse_error(
+    err: anyhow::Error,
+    line: &str,
+    on_parse_error: &ParseErrorPolicy,
+    max_size: usize,
+    source: &LineSource,
+    log_buffer: &mut LogBuffer,
+    stats: &mut ParseStats,
+) -> Result<()> {
+    stats.record_error(&err, line);
+    let last_entry = log_buffer.last_entry(source.id);
+
+    match on_parse_error {
+        ParseErrorPolicy::Drop => Err(err),
+        ParseErrorPolicy::AppendToPrevious if last_entry.is_some() => {
+            trace!("Appending unparsed line to the previous entry! {}", err);
+            append_continuation(log_buffer, source, line, max_size);
+            Ok(())
+        }
+        // Without a previous entry, the line is kept as is instead
+        ParseErrorPolicy::KeepRaw | ParseErrorPolicy::AppendToPrevious => {
+            trace!("Keeping unparsed line! {}", err);
+
+            // The previous entry's timestamp keeps the line in place among its neighbours
+            let timestamp = last_entry
+                .and_then(|i| log_buffer.get(i))
+                .map(|entry| entry.timestamp())
+                .unwrap_or_else(OffsetDateTime::now_utc);
+
+            log_buffer.push_from(
+                source.id,
+                LogEntry {
+                    timestamp,
+                    level: log::Level::Info,
+                    module: "N/A".to_string(),
+                    message: line.to_string(),
+                    fields: BTreeMap::new(),
+                    application: log_buffer.app_i(),
+                    parse_failed: true,
+                },
+            );
+            Ok(())
+        }
+    }
+}
+
+// Appends a continuation line to the message of the previous log entry from the same source
+fn append_continuation(
+    log_buffer: &mut LogBuffer,
+    source: &LineSource,
+    line: &str,
+    max_size: usize,
+) {
+    let last_entry = log_buffer
+        .last_entry(source.id)
+        .and_then(|i| log_buffer.get(i).map(|entry| (i, entry)));
+
+    match last_entry {
+        Some((i, entry)) if entry.message.len() + line.len() < max_size => {
+            log_buffer.append_to_message(i, line);
         }
+        Some(_) => trace!("Multiline entry is too large, dropping line: {}", line),
+        None => trace!("No previous entry to continue, dropping line: {}", line),
     }
 }
 
-fn create_default_map() -> ValueKind {
-    warn!("No application configurations found, proceeding with defaults.");
+// Application names are stored in a hashmap that maps an index to the name, new names get the next free index
+pub fn get_app_index(i_to_app: &mut HashMap<usize, String>, app_name: &str) -> usize {
+    if let Some((app_i, _)) = i_to_app
+        .iter()
+        .find(|(_, stored_app_name)| *stored_app_name == app_name)
+    {
+        *app_i
+    } else {
+        let app_i = i_to_app.len();
+        i_to_app.insert(app_i, app_name.to_string());
+        app_i
+    }
+}
 
-    let mut map = HashMap::new();
-    map.insert(
-        "path".to_string(),
-        Value::new(None, ValueKind::String("logpeek-logs".to_string())),
-    );
-    map.insert(
-        "parser".to_string(),
-        Value::new(
-            None,
-            ValueKind::String(
-                r"^(?P<timestamp>\S+) (?P<level>\S+) (?P<module>\S+) - (?P<message>.+)$"
-                    .to_string(),
-            ),
-        ),
-    );
-    map.insert(
-        "timeformat".to_string(),
-        Value::new(None, ValueKind::String("iso8601".to_string())),
-    );
-    map.insert(
-        "buffer_size".to_string(),
-        Value::new(None, ValueKind::U64(1_000_000)),
+pub fn create_buffer(
+    buffer_size: u64,
+    buffer_bytes: Option<u64>,
+    retention: Option<Duration>,
+    app_name: &str,
+    app_i: usize,
+    sysinfo: &mut System,
+) -> Result<LogBuffer> {
+    // This memory check is expensive, but it is only done once per application
+    let memory_required = get_memory_required(buffer_size, buffer_bytes);
+    let available_memory = get_available_memory(sysinfo);
+
+    if available_memory < memory_required {
+        bail!("{} MB is required to allocate buffer for {}, which is more than the currently available memory of {} MB!",
+            memory_required / 1_048_576, app_name, available_memory / 1_048_576);
+    } else if available_memory < memory_required * 2 {
+        warn!("{} MB is required to allocate buffer for {}, which is more than half of the currently available memory! \
+        Consider reducing the buffer sizes to avoid runtime out-of-memory issues.", memory_required / 1_048_576, app_name);
+    } else {
+        debug!(
+            "{} MB is required for application {}",
+            memory_required / 1_048_576,
+            app_name
+        );
+    }
+
+    let mut log_buffer = LogBuffer::new(
+        buffer_size as usize,
+        buffer_bytes.map(|buffer_bytes| buffer_bytes as usize),
+        retention,
+        app_i,
     );
 
-    ValueKind::Table(map)
+    store::open(&mut log_buffer, app_name)
+        .map_err(|err| anyhow!("Failed to open the store for {}! {}", app_name, err))?;
+    Ok(log_buffer)
+}
+
+impl FileState {
+    fn new(log_file: &LogFile, offset: u64) -> Self {
+        FileState {
+            path: log_file.path.clone(),
+            offset,
+            fingerprint: Fingerprint::read(&log_file.path, log_file.len.min(FINGERPRINT_SIZE)),
+            source: None,
+        }
+    }
+
+    // The file has shrunk or starts differently. If it has been written to again since it was truncated,
+    // only the start tells.
+    fn is_truncated(&self, log_file: &LogFile) -> bool {
+        log_file.len < self.offset
+            || (log_file.len > self.offset
+                && self.fingerprint.is_some_and(|fingerprint| {
+                    Fingerprint::read(&log_file.path, fingerprint.len) != Some(fingerprint)
+                }))
+    }
+}
+
+impl LineSource {
+    pub fn new(id: u64) -> Self {
+        LineSource {
+            id,
+            partial_messages: HashMap::new(),
+            held_back: None,
+        }
+    }
+
+    pub fn has_partial_messages(&self) -> bool {
+        !self.partial_messages.is_empty()
+    }
+}
+
+impl HeldBack {
+    fn was_read(&self, line: &str, line_start: u64, app: &Application) -> bool {
+        if line_start >= self.end {
+            return false;
+        }
+
+        // Lines that aren't container log lines were handled as such when they were read
+        match container::unwrap_line(line, &app.format) {
+            Ok(container_line) => self
+                .partial_starts
+                .get(&container_line.stream)
+                .is_none_or(|partial_start| line_start < *partial_start),
+            Err(_) => true,
+        }
+    }
+}
+
+impl Fingerprint {
+    // None if the file is empty or shorter than len
+    fn read(path: &Path, len: u64) -> Option<Self> {
+        if len == 0 {
+            return None;
+        }
+
+        let mut head = Vec::new();
+        fs::File::open(path)
+            .and_then(|file| file.take(len).read_to_end(&mut head))
+            .ok()?;
+
+        if (head.len() as u64) < len {
+            return None;
+        }
+
+        // FNV-1a, the fingerprints are kept in the store and have to stay the same between versions
+        let hash = head.iter().fold(0xcbf2_9ce4_8422_2325, |hash: u64, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        });
+
+        Some(Fingerprint { len, hash })
+    }
+}
+
+// A file that hasn't been read before, or was overwritten since, but starts the same as one that has is a copy of it
+fn find_original(cache: &HashMap<FileId, FileState>, log_file: &LogFile) -> Option<FileState> {
+    let mut fingerprints: HashMap<u64, Option<Fingerprint>> = HashMap::new();
+
+    cache
+        .iter()
+        .filter(|(id, _)| **id != log_file.id)
+        .map(|(_, state)| state)
+        .find(|state| {
+            state.fingerprint.is_some_and(|fingerprint| {
+                fingerprint.len <= log_file.len
+                    && *fingerprints
+                        .entry(fingerprint.len)
+                        .or_insert_with(|| Fingerprint::read(&log_file.path, fingerprint.len))
+                        == Some(fingerprint)
+            })
+        })
+        .cloned()
+}
+
+impl LogFile {
+    fn new(path: PathBuf) -> Option<Self> {
+        match metadata(&path) {
+            Ok(metadata) if metadata.is_file() => Some(LogFile {
+                id: FileId::from_metadata(&path, &metadata),
+                len: metadata.len(),
+                modified: metadata.modified().unwrap_or_else(|_| {
+                    error!("Failed to read modified time for log file! {:?}", path);
+                    SystemTime::UNIX_EPOCH
+                }),
+                path,
+            }),
+            Ok(_) => None,
+            Err(err) => {
+                error!("Failed to read log file metadata! {:?} {}", path, err);
+                None
+            }
+        }
+    }
+}
+
+impl FileId {
+    // Copies of a file share the source of the original, see find_original
+    fn source_id(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[cfg(unix)]
+    fn from_metadata(_path: &Path, metadata: &Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+
+        FileId {
+            device: metadata.dev(),
+            inode: metadata.ino(),
+        }
+    }
+
+    // Stable file indexes are not available on other platforms, so the path has to do
+    #[cfg(not(unix))]
+    fn from_metadata(path: &Path, _metadata: &Metadata) -> Self {
+        let mut hasher = DefaultHasher::new();
+        path.hash(&mut hasher);
+
+        FileId {
+            device: 0,
+            inode: hasher.finish(),
+        }
+    }
+}
+
+// Counts the complete entries in a file, only decoding them if the application has multiline entries.
+// Entries are single lines, except for the journal export format whose entries are counted as they are read.
+fn count_entries(path: &Path, app: &Application) -> usize {
+    let mut reader = match compression::open(path, 0) {
+        Ok((reader, _)) => reader,
+        Err(err) => {
+            error!("Failed to open log file {:?}! {}", path, err);
+            return 0;
+        }
+    };
+
+    if matches!(app.format, LogFormat::JournalExport) {
+        return journal::count_export_entries(&mut reader).unwrap_or_else(|err| {
+            error!("Failed to read log file {:?}! {}", path, err);
+            0
+        });
+    }
+
+    if app.encoding.is_utf16() || app.multiline.is_some() {
+        // Newlines are more than a byte or lines have to be looked at, so the file is read line by line
+        let mut line_buffer = Vec::new();
+        let mut entry_count = 0;
+
+        loop {
+            line_buffer.clear();
+
+            match app.encoding.read_line(&mut reader, &mut line_buffer) {
+                Ok(0) => break,
+                Ok(_)
+                    if app.encoding.ends_with_newline(&line_buffer)
+                        && starts_entry(&line_buffer, app) =>
+                {
+                    entry_count += 1
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    error!("Failed to read log file {:?}! {}", path, err);
+                    break;
+                }
+            }
+        }
+
+        return entry_count;
+    }
+
+    let mut entry_count = 0;
+
+    loop {
+        let chunk_len = match reader.fill_buf() {
+            Ok([]) => break,
+            Ok(chunk) => {
+                entry_count += chunk.iter().filter(|&&byte| byte == b'\n').count();
+                chunk.len()
+            }
+            Err(err) => {
+                error!("Failed to read log file {:?}! {}", path, err);
+                break;
+            }
+        };
+
+        reader.consume(chunk_len);
+    }
+
+    entry_count
+}
+
+// Returns the byte offset at which the given entry starts, in decompressed bytes for compressed files
+fn get_entry_offset(path: &Path, entry: usize, app: &Application) -> u64 {
+    let mut reader = match compression::open(path, 0) {
+        Ok((reader, _)) => reader,
+        Err(err) => {
+            error!("Failed to open log file {:?}! {}", path, err);
+            return 0;
+        }
+    };
+    let mut offset = 0;
+    let mut skipped = 0;
+
+    if matches!(app.format, LogFormat::JournalExport) {
+        while skipped < entry {
+            match journal::read_export_entry(&mut reader) {
+                Ok(Some((bytes_read, _))) => offset += bytes_read,
+                Ok(None) => break,
+                Err(err) => {
+                    error!("Failed to read log file {:?}! {}", path, err);
+                    break;
+                }
+            }
+
+            skipped += 1;
+        }
+
+        return offset;
+    }
+
+    let mut line_buffer = Vec::new();
+
+    loop {
+        line_buffer.clear();
+
+        let bytes_read = match app.encoding.read_line(&mut reader, &mut line_buffer) {
+            Ok(0) => break,
+            Ok(bytes_read) => bytes_read as u64,
+            Err(err) => {
+                error!("Failed to read log file {:?}! {}", path, err);
+                break;
+            }
+        };
+
+        if starts_entry(&line_buffer, app) {
+            // Continuation lines belong to the entry before them
+            if skipped == entry {
+                break;
+            }
+            skipped += 1;
+        }
+
+        offset += bytes_read;
+    }
+
+    offset
+}
+
+// Whether a line starts a new entry, by the same rules that are used when the line is read
+fn starts_entry(line_buffer: &[u8], app: &Application) -> bool {
+    let Some(multiline) = &app.multiline else {
+        return true;
+    };
+
+    // Lines that can't be decoded are never added
+    let line = match app.encoding.decode(line_buffer, app.lossy) {
+        Ok(line) => line,
+        Err(_) => return false,
+    };
+    let line = line
+        .trim_end_matches(['\n', '\r'])
+        .trim_start_matches('\u{feff}');
+
+    let container_line = match app.format {
+        LogFormat::Docker(_) | LogFormat::Cri(_) => match container::unwrap_line(line, &app.format)
+        {
+            // Only the final part of a split line completes an entry
+            Ok(container_line) if container_line.is_partial => return false,
+            Ok(container_line) => Some(container_line),
+            Err(_) => return true,
+        },
+        _ => None,
+    };
+    let line = container_line
+        .as_ref()
+        .map_or(line, |container_line| container_line.message.as_str());
+
+    match multiline {
+        Multiline::Continuation(continuation_re) => !continuation_re.is_match(line),
+        Multiline::Unmatched => {
+            let mut stats = ParseStats::default();
+            match &container_line {
+                Some(container_line) => {
+                    parser::parse_container_entry(container_line, app, 0, &mut stats).is_ok()
+                }
+                None => parser::parse_entry(line, app, 0, &mut stats).is_ok(),
+            }
+        }
+    }
 }
 
 fn get_available_memory(sysinfo: &mut System) -> u64 {
@@ -333,6 +1274,135 @@ fn get_available_memory(sysinfo: &mut System) -> u64 {
     sysinfo.total_memory() - sysinfo.used_memory()
 }
 
-fn get_memory_required<T>(buffer_size: u64) -> u64 {
-    buffer_size * size_of::<T>() as u64
+// Without buffer_bytes, the size of the messages has to be guessed
+fn get_memory_required(buffer_size: u64, buffer_bytes: Option<u64>) -> u64 {
+    let memory_required = buffer_size
+        .saturating_mul(LogBuffer::entry_size() as u64 + AVERAGE_MESSAGE_SIZE)
+        .min(buffer_bytes.unwrap_or(u64::MAX));
+
+    match *MAX_MEMORY {
+        Some(max_memory) => memory_required.min(max_memory as u64),
+        None => memory_required,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use config::{Value, ValueKind};
+
+    use super::*;
+
+    fn cri_app() -> Application {
+        let table: HashMap<String, Value> = [("name", "cri"), ("format", "cri")]
+            .iter()
+            .map(|(key, value)| {
+                (
+                    key.to_string(),
+                    Value::new(None, ValueKind::String(value.to_string())),
+                )
+            })
+            .collect();
+
+        Application::from_config(Value::new(None, ValueKind::Table(table))).unwrap()
+    }
+
+    fn cri_line(stream: &str, tag: &str, message: &str) -> String {
+        format!(
+            "2024-01-17T13:55:30.123456789Z {} {} {}\n",
+            stream, tag, message
+        )
+    }
+
+    // Reads the lines from the offset on, as if the rest of the file was written later
+    fn read(
+        content: &str,
+        offset: u64,
+        app: &Application,
+        source: &mut LineSource,
+        log_buffer: &mut LogBuffer,
+    ) -> u64 {
+        let mut reader = &content.as_bytes()[offset as usize..];
+        let (offset, stats) = read_lines(
+            &mut reader,
+            offset,
+            Path::new("test.log"),
+            false,
+            app,
+            source,
+            log_buffer,
+        );
+
+        assert!(stats.is_empty());
+        offset
+    }
+
+    fn messages(log_buffer: &LogBuffer) -> Vec<String> {
+        (0..log_buffer.len())
+            .map(|i| log_buffer.get(i).unwrap().message.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn partial_container_lines() {
+        let app = cri_app();
+        let mut source = LineSource::new(0);
+        let mut log_buffer = LogBuffer::new(10, None, None, 0);
+
+        let content = [
+            cri_line("stdout", "P", "hel"),
+            cri_line("stderr", "F", "other"),
+            cri_line("stdout", "F", "lo"),
+        ]
+        .concat();
+
+        let offset = read(&content, 0, &app, &mut source, &mut log_buffer);
+        assert_eq!(offset, content.len() as u64);
+        assert!(source.held_back.is_none());
+        assert_eq!(messages(&log_buffer), ["other", "hello"]);
+    }
+
+    #[test]
+    fn held_back_partial_lines() {
+        let app = cri_app();
+        let mut source = LineSource::new(0);
+        let mut log_buffer = LogBuffer::new(10, None, None, 0);
+
+        let first = cri_line("stdout", "F", "first");
+        let mut content = [
+            first.clone(),
+            cri_line("stdout", "P", "par"),
+            cri_line("stderr", "F", "other"),
+        ]
+        .concat();
+
+        // Reading continues at the partial line, the lines of the other stream after it were already read
+        let offset = read(&content, 0, &app, &mut source, &mut log_buffer);
+        assert_eq!(offset, first.len() as u64);
+        assert_eq!(messages(&log_buffer), ["first", "other"]);
+
+        content.push_str(&cri_line("stdout", "F", "tial"));
+        let offset = read(&content, offset, &app, &mut source, &mut log_buffer);
+        assert_eq!(offset, content.len() as u64);
+        assert!(source.held_back.is_none());
+        assert_eq!(messages(&log_buffer), ["first", "other", "partial"]);
+    }
+
+    #[test]
+    fn partial_message_too_large() {
+        let app = cri_app();
+        let mut source = LineSource::new(0);
+        let mut log_buffer = LogBuffer::new(10, None, None, 0);
+
+        let part = "x".repeat(MAX_PARTIAL_MESSAGE_SIZE / 2);
+        let content = [
+            cri_line("stdout", "P", &part),
+            cri_line("stdout", "P", &part),
+            cri_line("stdout", "F", "end"),
+        ]
+        .concat();
+
+        let offset = read(&content, 0, &app, &mut source, &mut log_buffer);
+        assert_eq!(offset, content.len() as u64);
+        assert_eq!(messages(&log_buffer), [part.repeat(2), "end".to_string()]);
+    }
 }
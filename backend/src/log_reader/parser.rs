@@ -1,14 +1,20 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::str::FromStr;
 
 use anyhow::Result;
 use log::warn;
 use regex::Regex;
 use thiserror::Error;
-use time::format_description::well_known::Iso8601;
-use time::OffsetDateTime;
+use time::format_description::well_known::{Iso8601, Rfc2822, Rfc3339};
+use time::parsing::Parsed;
+use time::{Duration, OffsetDateTime, PrimitiveDateTime};
+use time_tz::{OffsetDateTimeExt, OffsetResult, PrimitiveDateTimeExt, Tz};
 
-use crate::log_reader::TimeFormat;
+use crate::log_reader::application::Application;
+use crate::log_reader::container::ContainerLine;
+use crate::log_reader::stats::ParseStats;
+use crate::log_reader::{journal, FieldNames, LogFormat, TimeFormat};
 use crate::LogEntry;
 
 #[derive(Debug, Error)]
@@ -17,87 +23,646 @@ pub enum LogParseError {
     NoCaptureGroupsFound(String),
     #[error("Invalid message: {0}")]
     InvalidMessage(String),
+    #[error("Invalid JSON: {0}")]
+    InvalidJson(String),
+    #[error("Invalid timestamp: {0}")]
+    InvalidTimestamp(String),
+    #[error("Invalid {0} encoding")]
+    InvalidEncoding(String),
 }
 
+// Values extracted from a log line before they are converted into a log entry
+struct RawEntry<'a> {
+    timestamp: Option<&'a str>,
+    level: Option<&'a str>,
+    module: Option<&'a str>,
+    message: &'a str,
+    fields: BTreeMap<String, String>,
+}
+
+// Problems that don't prevent the line from being parsed, such as unknown levels, are recorded in stats
 pub fn parse_entry(
+    line: &str,
+    app: &Application,
+    app_i: usize,
+    stats: &mut ParseStats,
+) -> Result<LogEntry> {
+    match &app.format {
+        LogFormat::Regex(parser_re) => parse_regex_entry(line, parser_re, app, app_i, stats),
+        LogFormat::Json(fields) => parse_json_entry(line, fields, app, app_i, stats),
+        LogFormat::Logfmt(fields) => parse_logfmt_entry(line, fields, app, app_i, stats),
+        LogFormat::JournalJson => journal::parse_json_entry(line, app_i),
+        // Export entries span multiple lines and are read with journal::read_export_entry instead
+        LogFormat::JournalExport => Err(LogParseError::InvalidMessage(line.to_string()).into()),
+        // Container lines are unwrapped first and then parsed with parse_container_entry
+        LogFormat::Docker(_) | LogFormat::Cri(_) => {
+            Err(LogParseError::InvalidMessage(line.to_string()).into())
+        }
+    }
+}
+
+fn parse_regex_entry(
     line: &str,
     parser_re: &Regex,
-    timeformat: &TimeFormat,
+    app: &Application,
     app_i: usize,
-    level_map: &Option<HashMap<String, String>>,
+    stats: &mut ParseStats,
 ) -> Result<LogEntry> {
+    build_entry(
+        get_regex_raw_entry(line, parser_re)?,
+        line,
+        app,
+        app_i,
+        stats,
+    )
+}
+
+fn get_regex_raw_entry<'a>(line: &'a str, parser_re: &Regex) -> Result<RawEntry<'a>> {
     if let Some(caps) = parser_re.captures(line) {
-        let timestamp = if let Some(timestamp) = caps.name("timestamp") {
-            match timeformat {
-                TimeFormat::Iso8601 => {
-                    OffsetDateTime::parse(timestamp.as_str(), &Iso8601::DEFAULT)?
-                }
-                TimeFormat::Rfc2822 => OffsetDateTime::parse(
-                    timestamp.as_str(),
-                    &time::format_description::well_known::Rfc2822,
-                )?,
-                TimeFormat::Rfc3339 => OffsetDateTime::parse(
-                    timestamp.as_str(),
-                    &time::format_description::well_known::Rfc3339,
-                )?,
-                TimeFormat::Custom(format_desc) => {
-                    OffsetDateTime::parse(timestamp.as_str(), &format_desc)?
+        let message = caps
+            .name("message")
+            .ok_or_else(|| LogParseError::InvalidMessage(line.to_string()))?
+            .as_str(); // Is required
+
+        // Named groups besides the standard ones are kept as additional fields
+        let fields = parser_re
+            .capture_names()
+            .flatten()
+            .filter(|name| !matches!(*name, "timestamp" | "level" | "module" | "message"))
+            .filter_map(|name| {
+                caps.name(name)
+                    .map(|value| (name.to_string(), value.as_str().to_string()))
+            })
+            .collect();
+
+        Ok(RawEntry {
+            timestamp: caps.name("timestamp").map(|timestamp| timestamp.as_str()),
+            level: caps.name("level").map(|level| level.as_str()),
+            module: caps.name("module").map(|module| module.as_str()),
+            message,
+            fields,
+        })
+    } else {
+        Err(LogParseError::NoCaptureGroupsFound(line.to_string()).into())
+    }
+}
+
+// The message inside a container log envelope is optionally parsed further with the application's regex.
+// The envelope's timestamp is used unless the regex captures one itself.
+pub fn parse_container_entry(
+    container_line: &ContainerLine,
+    app: &Application,
+    app_i: usize,
+    stats: &mut ParseStats,
+) -> Result<LogEntry> {
+    let mut raw_entry = match &app.format {
+        LogFormat::Docker(Some(parser_re)) | LogFormat::Cri(Some(parser_re)) => {
+            get_regex_raw_entry(&container_line.message, parser_re)?
+        }
+        _ => RawEntry {
+            timestamp: None,
+            level: None,
+            module: None,
+            message: &container_line.message,
+            fields: BTreeMap::new(),
+        },
+    };
+
+    let has_timestamp = raw_entry.timestamp.is_some();
+    raw_entry
+        .fields
+        .insert("stream".to_string(), container_line.stream.clone());
+
+    let mut entry = build_entry(raw_entry, &container_line.message, app, app_i, stats)?;
+    if !has_timestamp {
+        entry.timestamp = container_line.timestamp;
+    }
+
+    Ok(entry)
+}
+
+fn parse_json_entry(
+    line: &str,
+    fields: &FieldNames,
+    app: &Application,
+    app_i: usize,
+    stats: &mut ParseStats,
+) -> Result<LogEntry> {
+    let json: serde_json::Value = serde_json::from_str(line)
+        .map_err(|err| LogParseError::InvalidJson(format!("{} in line: {}", err, line)))?;
+
+    if !json.is_object() {
+        return Err(LogParseError::InvalidJson(format!("Not an object: {}", line)).into());
+    }
+
+    let timestamp = get_json_field(&json, &fields.timestamp);
+    let level = get_json_field(&json, &fields.level);
+    let module = get_json_field(&json, &fields.module);
+    let message = get_json_field(&json, &fields.message)
+        .ok_or_else(|| LogParseError::InvalidMessage(line.to_string()))?; // Is required
+
+    build_entry(
+        RawEntry {
+            timestamp: timestamp.as_deref(),
+            level: level.as_deref(),
+            module: module.as_deref(),
+            message: &message,
+            fields: BTreeMap::new(),
+        },
+        line,
+        app,
+        app_i,
+        stats,
+    )
+}
+
+fn parse_logfmt_entry(
+    line: &str,
+    fields: &FieldNames,
+    app: &Application,
+    app_i: usize,
+    stats: &mut ParseStats,
+) -> Result<LogEntry> {
+    let mut pairs: BTreeMap<String, String> = parse_logfmt(line).into_iter().collect();
+
+    if pairs.is_empty() {
+        return Err(LogParseError::NoCaptureGroupsFound(line.to_string()).into());
+    }
+
+    let timestamp = pairs.remove(&fields.timestamp);
+    let level = pairs.remove(&fields.level);
+    let module = pairs.remove(&fields.module);
+    let message = pairs
+        .remove(&fields.message)
+        .ok_or_else(|| LogParseError::InvalidMessage(line.to_string()))?; // Is required
+
+    // Whatever is left over is kept around for filtering
+    build_entry(
+        RawEntry {
+            timestamp: timestamp.as_deref(),
+            level: level.as_deref(),
+            module: module.as_deref(),
+            message: &message,
+            fields: pairs,
+        },
+        line,
+        app,
+        app_i,
+        stats,
+    )
+}
+
+// Splits a line like `level=warn msg="Slow query" took=5s` into key/value pairs.
+// Keys without a value are treated as flags and get "true" as their value.
+fn parse_logfmt(line: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '=') {
+            key.push(c);
+        }
+
+        if chars.next_if_eq(&'=').is_none() {
+            if key.is_empty() {
+                break;
+            }
+
+            pairs.push((key, "true".to_string()));
+            continue;
+        }
+
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => match chars.next() {
+                        Some('n') => value.push('\n'),
+                        Some('t') => value.push('\t'),
+                        Some(escaped) => value.push(escaped),
+                        None => break,
+                    },
+                    _ => value.push(c),
                 }
             }
         } else {
-            OffsetDateTime::now_utc()
-        };
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                value.push(c);
+            }
+        }
+
+        if !key.is_empty() {
+            pairs.push((key, value));
+        }
+    }
+
+    pairs
+}
+
+// Fields can be nested, "log.level" first looks for that exact key and then for {"log": {"level": ...}}
+fn get_json_field<'a>(json: &'a serde_json::Value, path: &str) -> Option<Cow<'a, str>> {
+    let value = json
+        .get(path)
+        .or_else(|| path.split('.').try_fold(json, |value, key| value.get(key)))?;
+
+    match value {
+        serde_json::Value::Null => None,
+        serde_json::Value::String(string) => Some(Cow::Borrowed(string.as_str())),
+        other => Some(Cow::Owned(other.to_string())),
+    }
+}
+
+fn build_entry(
+    raw_entry: RawEntry,
+    line: &str,
+    app: &Application,
+    app_i: usize,
+    stats: &mut ParseStats,
+) -> Result<LogEntry> {
+    let RawEntry {
+        timestamp,
+        level,
+        module,
+        message,
+        fields,
+    } = raw_entry;
+
+    let timestamp = if let Some(timestamp) = timestamp {
+        parse_timestamp(timestamp, &app.timeformat, app.timezone).map_err(|err| {
+            LogParseError::InvalidTimestamp(format!("{} ({}) in line: {}", timestamp, err, line))
+        })?
+    } else {
+        OffsetDateTime::now_utc()
+    };
 
-        let level = caps
-            .name("level")
-            .and_then(|level| {
-                let level_str = level.as_str();
-
-                level_map
-                    .as_ref()
-                    .and_then(|map| map.get(level_str).cloned())
-                    .map(|mapped_level| {
-                        log::Level::from_str(&mapped_level).unwrap_or_else(|_| {
-                            warn!(
-                                "Invalid log level mapping: {}. Using INFO instead",
-                                mapped_level
-                            );
-                            log::Level::Info
-                        })
+    let level = level
+        .and_then(|level_str| {
+            app.level_map
+                .as_ref()
+                .and_then(|map| map.get(level_str).cloned())
+                .map(|mapped_level| {
+                    log::Level::from_str(&mapped_level).unwrap_or_else(|_| {
+                        warn!(
+                            "Invalid log level mapping: {}. Using INFO instead",
+                            mapped_level
+                        );
+                        stats.record_invalid_level(&mapped_level, line);
+                        log::Level::Info
                     })
-                    .or_else(|| {
-                        let from_str_result = log::Level::from_str(level_str);
-                        
-                        match from_str_result {
-                            Ok(level) => Some(level),
-                            Err(_) => {
-                                warn!("Invalid log level: {}. Consider adding a mapping for it under the applications level_map field.", level_str);
-                                None
-                            }
+                })
+                .or_else(|| {
+                    let from_str_result = log::Level::from_str(level_str);
+
+                    match from_str_result {
+                        Ok(level) => Some(level),
+                        Err(_) => {
+                            warn!("Invalid log level: {}. Consider adding a mapping for it under the applications level_map field.", level_str);
+                            stats.record_invalid_level(level_str, line);
+                            None
                         }
-                    })
+                    }
+                })
+        })
+        .unwrap_or(log::Level::Info);
+
+    Ok(LogEntry {
+        timestamp,
+        level,
+        module: module.unwrap_or("N/A").to_string(),
+        message: message.to_string(),
+        fields,
+        application: app_i,
+        parse_failed: false,
+    })
+}
+
+// Timestamps without an offset are in the application's timezone, or UTC if there is none
+fn parse_timestamp(
+    timestamp: &str,
+    timeformat: &TimeFormat,
+    timezone: Option<&Tz>,
+) -> Result<OffsetDateTime> {
+    let parsed = match timeformat {
+        TimeFormat::Iso8601 => match OffsetDateTime::parse(timestamp, &Iso8601::DEFAULT) {
+            Ok(parsed) => parsed,
+            Err(_) => assume_timezone(
+                PrimitiveDateTime::parse(timestamp, &Iso8601::DEFAULT)?,
+                timezone,
+            ),
+        },
+        TimeFormat::Rfc2822 => OffsetDateTime::parse(timestamp, &Rfc2822)?,
+        TimeFormat::Rfc3339 => OffsetDateTime::parse(timestamp, &Rfc3339)?,
+        TimeFormat::EpochS => parse_epoch(timestamp, 1_000_000_000)?,
+        TimeFormat::EpochMs => parse_epoch(timestamp, 1_000_000)?,
+        TimeFormat::EpochNs => parse_epoch(timestamp, 1)?,
+        TimeFormat::Custom(format_desc) => {
+            let mut parsed = Parsed::new();
+            let remaining = parsed.parse_item(timestamp.as_bytes(), format_desc)?;
+
+            if !remaining.is_empty() {
+                return Err(LogParseError::InvalidTimestamp(timestamp.to_string()).into());
+            }
+
+            if parsed.year().is_none() {
+                infer_year(&mut parsed, timezone)?;
+            }
+
+            parsed_to_datetime(parsed, timezone)?
+        }
+    };
+
+    Ok(parsed)
+}
+
+// Formats like syslog's "Jan 17 13:55:36" have no year, the current one is assumed unless that would put the
+// timestamp more than a day into the future, in which case it is from the previous year
+fn infer_year(parsed: &mut Parsed, timezone: Option<&Tz>) -> Result<()> {
+    let now = match timezone {
+        Some(timezone) => OffsetDateTime::now_utc().to_timezone(timezone),
+        None => OffsetDateTime::now_utc(),
+    };
+
+    for year in [now.year(), now.year() - 1] {
+        parsed
+            .set_year(year)
+            .ok_or_else(|| LogParseError::InvalidTimestamp(format!("{:?}", parsed)))?;
+
+        if parsed_to_datetime(*parsed, timezone)? <= now + Duration::days(1) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn parsed_to_datetime(parsed: Parsed, timezone: Option<&Tz>) -> Result<OffsetDateTime> {
+    if parsed.offset_hour().is_some() {
+        Ok(OffsetDateTime::try_from(parsed)?)
+    } else {
+        Ok(assume_timezone(
+            PrimitiveDateTime::try_from(parsed)?,
+            timezone,
+        ))
+    }
+}
+
+// During the DST transition in autumn the earlier time is used. Times skipped in spring don't exist,
+// so they get the offset in effect at the same time in UTC instead.
+fn assume_timezone(datetime: PrimitiveDateTime, timezone: Option<&Tz>) -> OffsetDateTime {
+    match timezone {
+        Some(timezone) => match datetime.assume_timezone(timezone) {
+            OffsetResult::Some(datetime) | OffsetResult::Ambiguous(datetime, _) => datetime,
+            OffsetResult::None => datetime.assume_timezone_utc(timezone),
+        },
+        None => datetime.assume_utc(),
+    }
+}
+
+// Unix timestamps, optionally with a fractional part, e.g. "1705499736.123" for seconds
+fn parse_epoch(timestamp: &str, nanos_per_unit: i128) -> Result<OffsetDateTime> {
+    let invalid_timestamp = || LogParseError::InvalidTimestamp(timestamp.to_string());
+
+    let (whole, fraction) = timestamp.split_once('.').unwrap_or((timestamp, ""));
+    if !fraction.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Err(invalid_timestamp().into());
+    }
+
+    let whole: i128 = whole.parse().map_err(|_| invalid_timestamp())?;
+    let mut nanos = whole
+        .checked_mul(nanos_per_unit)
+        .ok_or_else(invalid_timestamp)?;
+
+    if !fraction.is_empty() && nanos_per_unit > 1 {
+        // Digits beyond nanosecond precision are ignored
+        let digits = fraction.len().min(nanos_per_unit.ilog10() as usize);
+        let fraction: i128 = fraction[..digits]
+            .parse()
+            .map_err(|_| invalid_timestamp())?;
+        let fraction_nanos = fraction * nanos_per_unit / 10_i128.pow(digits as u32);
+
+        nanos = nanos
+            .checked_add(if timestamp.starts_with('-') {
+                -fraction_nanos
+            } else {
+                fraction_nanos
             })
-            .unwrap_or(log::Level::Info);
+            .ok_or_else(invalid_timestamp)?;
+    }
 
-        let module = if let Some(module) = caps.name("module") {
-            module.as_str()
-        } else {
-            "N/A"
+    Ok(OffsetDateTime::from_unix_timestamp_nanos(nanos).map_err(|_| invalid_timestamp())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use config::{Value, ValueKind};
+
+    use super::*;
+
+    fn is_invalid_timestamp(result: Result<OffsetDateTime>) -> bool {
+        matches!(
+            result.unwrap_err().downcast_ref(),
+            Some(LogParseError::InvalidTimestamp(_))
+        )
+    }
+
+    fn logfmt_application() -> Application {
+        let mut table = HashMap::new();
+        for (key, value) in [("name", "test"), ("format", "logfmt")] {
+            table.insert(
+                key.to_string(),
+                Value::new(None, ValueKind::String(value.to_string())),
+            );
+        }
+
+        Application::from_config(Value::new(None, ValueKind::Table(table))).unwrap()
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn logfmt_pairs() {
+        assert_eq!(
+            parse_logfmt(r#"level=warn msg="Slow query" took=5s"#),
+            pairs(&[("level", "warn"), ("msg", "Slow query"), ("took", "5s")])
+        );
+        assert_eq!(
+            parse_logfmt(r#"  msg="a \"quoted\" \\ value\nnext\tcolumn"   "#),
+            pairs(&[("msg", "a \"quoted\" \\ value\nnext\tcolumn")])
+        );
+        assert_eq!(
+            parse_logfmt("msg=hi debug empty= url=http://host/?a=b"),
+            pairs(&[
+                ("msg", "hi"),
+                ("debug", "true"),
+                ("empty", ""),
+                ("url", "http://host/?a=b")
+            ])
+        );
+        assert_eq!(
+            parse_logfmt("msg=ü ä=ö"),
+            pairs(&[("msg", "ü"), ("ä", "ö")])
+        );
+    }
+
+    #[test]
+    fn logfmt_malformed() {
+        assert!(parse_logfmt("").is_empty());
+        assert!(parse_logfmt("   ").is_empty());
+        assert!(parse_logfmt("=value").is_empty());
+        assert_eq!(
+            parse_logfmt(r#"msg="unterminated value"#),
+            pairs(&[("msg", "unterminated value")])
+        );
+        assert_eq!(
+            parse_logfmt(r#"msg="trailing\"#),
+            pairs(&[("msg", "trailing")])
+        );
+        assert_eq!(
+            parse_logfmt(r#"msg="a"b=c"#),
+            pairs(&[("msg", "a"), ("b", "c")])
+        );
+    }
+
+    #[test]
+    fn logfmt_entry() {
+        let app = logfmt_application();
+        let mut stats = ParseStats::default();
+
+        let entry = parse_entry(
+            r#"ts=2024-01-17T13:55:30Z level=warn module=db msg="Slow query" took=5s"#,
+            &app,
+            3,
+            &mut stats,
+        )
+        .unwrap();
+
+        assert_eq!(
+            entry.timestamp,
+            OffsetDateTime::parse("2024-01-17T13:55:30Z", &Rfc3339).unwrap()
+        );
+        assert_eq!(entry.level, log::Level::Warn);
+        assert_eq!(entry.module, "db");
+        assert_eq!(entry.message, "Slow query");
+        assert_eq!(entry.application, 3);
+        assert_eq!(entry.fields.len(), 1);
+        assert_eq!(entry.fields["took"], "5s");
+        assert!(stats.is_empty());
+    }
+
+    #[test]
+    fn logfmt_entry_malformed() {
+        let app = logfmt_application();
+        let mut stats = ParseStats::default();
+        let error = |line: &str, stats: &mut ParseStats| {
+            parse_entry(line, &app, 0, stats)
+                .unwrap_err()
+                .downcast::<LogParseError>()
+                .unwrap()
         };
 
-        let message = caps
-            .name("message")
-            .ok_or_else(|| LogParseError::InvalidMessage(line.to_string()))?
-            .as_str(); // Is required
+        assert!(matches!(
+            error("   ", &mut stats),
+            LogParseError::NoCaptureGroupsFound(_)
+        ));
+        assert!(matches!(
+            error("level=info module=db", &mut stats),
+            LogParseError::InvalidMessage(_)
+        ));
+        assert!(matches!(
+            error("ts=yesterday msg=hi", &mut stats),
+            LogParseError::InvalidTimestamp(_)
+        ));
 
-        Ok(LogEntry {
-            timestamp,
-            level,
-            module: module.to_string(),
-            message: message.to_string(),
-            application: app_i,
-        })
-    } else {
-        Err(LogParseError::NoCaptureGroupsFound(line.to_string()).into())
+        // Unknown levels are replaced and recorded
+        let entry = parse_entry("level=loud msg=hi", &app, 0, &mut stats).unwrap();
+        assert_eq!(entry.level, log::Level::Info);
+        assert_eq!(stats.invalid_levels, 1);
+    }
+
+    #[test]
+    fn parse_epoch_units() {
+        let expected =
+            OffsetDateTime::from_unix_timestamp_nanos(1_705_499_736_123_000_000).unwrap();
+
+        assert_eq!(
+            parse_epoch("1705499736.123", 1_000_000_000).unwrap(),
+            expected
+        );
+        assert_eq!(parse_epoch("1705499736123", 1_000_000).unwrap(), expected);
+        assert_eq!(parse_epoch("1705499736123000000", 1).unwrap(), expected);
+    }
+
+    #[test]
+    fn parse_epoch_fraction() {
+        assert_eq!(
+            parse_epoch("1705499736.1234567891234", 1_000_000_000)
+                .unwrap()
+                .unix_timestamp_nanos(),
+            1_705_499_736_123_456_789
+        );
+        assert_eq!(
+            parse_epoch("1705499736123.5", 1_000_000)
+                .unwrap()
+                .unix_timestamp_nanos(),
+            1_705_499_736_123_500_000
+        );
+        assert_eq!(
+            parse_epoch("-1.5", 1_000_000_000)
+                .unwrap()
+                .unix_timestamp_nanos(),
+            -1_500_000_000
+        );
+    }
+
+    #[test]
+    fn parse_epoch_overflow() {
+        assert!(is_invalid_timestamp(parse_epoch(
+            "170549973612345678901234567890123",
+            1_000_000_000
+        )));
+        assert!(is_invalid_timestamp(parse_epoch(
+            "-170549973612345678901234567890123.5",
+            1_000_000_000
+        )));
+        assert!(is_invalid_timestamp(parse_epoch(
+            "1234567890123456789012345678901234567890",
+            1
+        )));
+        // Fits into i128 but not into OffsetDateTime
+        assert!(is_invalid_timestamp(parse_epoch(
+            "99999999999999999",
+            1_000_000_000
+        )));
+    }
+
+    #[test]
+    fn parse_epoch_malformed() {
+        assert!(is_invalid_timestamp(parse_epoch(
+            "1705499736.12345é",
+            1_000_000
+        )));
+        assert!(is_invalid_timestamp(parse_epoch(
+            "1705499736.éé",
+            1_000_000_000
+        )));
+        assert!(is_invalid_timestamp(parse_epoch("1705499736.12a", 1)));
+        assert!(is_invalid_timestamp(parse_epoch(
+            "1705499736.-5",
+            1_000_000_000
+        )));
+        assert!(is_invalid_timestamp(parse_epoch("", 1_000_000_000)));
+        assert!(is_invalid_timestamp(parse_epoch("abc", 1_000_000_000)));
+        assert!(is_invalid_timestamp(parse_epoch("1.2.3", 1_000_000_000)));
     }
 }
@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::log_reader::application::Application;
+use crate::log_reader::stats::ParseStats;
+use crate::log_reader::{container, parser, LogFormat, Multiline};
+
+#[derive(Debug, Serialize)]
+pub struct ParserTestResult {
+    pub parsed: usize,
+    pub failed: usize,
+    pub lines: Vec<ParsedLine>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ParsedLine {
+    pub line_number: usize,
+    pub line: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub entry: Option<ParsedEntry>,
+    // Continuation lines are appended to the previous entry instead of being parsed
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub continuation: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ParsedEntry {
+    #[serde(with = "time::serde::rfc3339")]
+    pub timestamp: OffsetDateTime,
+    pub level: log::Level,
+    pub module: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, String>,
+}
+
+// Parses every line of the input on its own with the application's parser, nothing is added to the log buffers
+pub fn test_parser(input: &str, app: &Application) -> ParserTestResult {
+    let mut result = ParserTestResult {
+        parsed: 0,
+        failed: 0,
+        lines: Vec::new(),
+    };
+
+    for (i, line) in input.lines().enumerate() {
+        let mut line = line.trim_end_matches('\r');
+
+        if i == 0 {
+            line = line.trim_start_matches('\u{feff}');
+        }
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let parsed_line = test_line(line, i + 1, app);
+
+        if parsed_line.entry.is_some() {
+            result.parsed += 1;
+        } else if parsed_line.error.is_some() {
+            result.failed += 1;
+        }
+
+        result.lines.push(parsed_line);
+    }
+
+    result
+}
+
+fn test_line(line: &str, line_number: usize, app: &Application) -> ParsedLine {
+    let mut parsed_line = ParsedLine {
+        line_number,
+        line: line.to_string(),
+        entry: None,
+        continuation: false,
+        error: None,
+        warnings: Vec::new(),
+    };
+
+    // Same steps as process_line, except that partial container lines are not reassembled
+    let container_line = match &app.format {
+        LogFormat::Docker(_) | LogFormat::Cri(_) => match container::unwrap_line(line, &app.format)
+        {
+            Ok(container_line) => {
+                if container_line.is_partial {
+                    parsed_line.warnings.push(
+                        "Partial container line, the message continues on the next line"
+                            .to_string(),
+                    );
+                }
+
+                Some(container_line)
+            }
+            Err(err) => {
+                parsed_line.error = Some(err.to_string());
+                return parsed_line;
+            }
+        },
+        LogFormat::JournalExport => {
+            parsed_line.error = Some(
+                "Journal export entries span multiple lines and can't be tested line by line"
+                    .to_string(),
+            );
+            return parsed_line;
+        }
+        _ => None,
+    };
+
+    let message = container_line
+        .as_ref()
+        .map_or(line, |container_line| container_line.message.as_str());
+
+    if let Some(Multiline::Continuation(continuation_re)) = &app.multiline {
+        if continuation_re.is_match(message) {
+            parsed_line.continuation = true;
+            return parsed_line;
+        }
+    }
+
+    let mut stats = ParseStats::default();
+    let parse_result = match &container_line {
+        Some(container_line) => parser::parse_container_entry(container_line, app, 0, &mut stats),
+        None => parser::parse_entry(line, app, 0, &mut stats),
+    };
+
+    match parse_result {
+        Ok(entry) => {
+            parsed_line.entry = Some(ParsedEntry {
+                timestamp: entry.timestamp,
+                level: entry.level,
+                module: entry.module,
+                message: entry.message,
+                fields: entry.fields,
+            });
+        }
+        // Any line that doesn't parse continues the previous entry
+        Err(_) if matches!(app.multiline, Some(Multiline::Unmatched)) => {
+            parsed_line.continuation = true;
+        }
+        Err(err) => parsed_line.error = Some(err.to_string()),
+    }
+
+    parsed_line
+        .warnings
+        .extend(stats.samples.into_iter().map(|sample| sample.error));
+
+    parsed_line
+}
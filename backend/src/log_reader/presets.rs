@@ -0,0 +1,237 @@
+use anyhow::{Context, Result};
+use config::{Map, Value, ValueKind};
+
+// Parser settings for common log formats, selected with preset = "<name>" on an application
+struct Preset {
+    parser: &'static str,
+    timeformat: &'static str,
+    level_map: &'static [(&'static str, &'static str)],
+    multiline: Option<&'static str>,
+}
+
+// Levels that log::Level can't parse by itself, the standard ones are matched case-insensitively
+const SYSLOG_LEVELS: &[(&str, &str)] = &[
+    ("emerg", "ERROR"),
+    ("alert", "ERROR"),
+    ("crit", "ERROR"),
+    ("notice", "INFO"),
+];
+
+// The status code's first digit is captured as the level of access log entries
+const HTTP_STATUS_LEVELS: &[(&str, &str)] = &[
+    ("1", "INFO"),
+    ("2", "INFO"),
+    ("3", "INFO"),
+    ("4", "WARN"),
+    ("5", "ERROR"),
+];
+
+const ACCESS_LOG: Preset = Preset {
+    // Combined log format, the common log format without referer and user agent is matched as well
+    parser: r#"^(?P<remote_addr>\S+) \S+ (?P<remote_user>\S+) \[(?P<timestamp>[^\]]+)\] "(?P<message>[^"]*)" (?P<status>(?P<level>[1-5])\d\d) (?P<body_bytes>\S+)(?: "(?P<referer>[^"]*)" "(?P<user_agent>[^"]*)")?"#,
+    timeformat: "[day]/[month repr:short]/[year]:[hour]:[minute]:[second] [offset_hour sign:mandatory][offset_minute]",
+    level_map: HTTP_STATUS_LEVELS,
+    multiline: None,
+};
+
+const PRESETS: &[(&str, Preset)] = &[
+    (
+        // 2024-01-17T13:55:30.123456789Z INFO logpeek_server - Starting...
+        "logpeek",
+        Preset {
+            parser: r"^(?P<timestamp>\S+) (?P<level>\S+) (?P<module>\S+) - (?P<message>.+)$",
+            timeformat: "iso8601",
+            level_map: &[],
+            multiline: None,
+        },
+    ),
+    ("nginx_combined", ACCESS_LOG),
+    ("apache_combined", ACCESS_LOG),
+    (
+        // 2024/01/17 13:55:30 [error] 1234#1234: *5 open() "/srv/favicon.ico" failed, client: 127.0.0.1
+        "nginx_error",
+        Preset {
+            parser: r"^(?P<timestamp>\d{4}/\d{2}/\d{2} \d{2}:\d{2}:\d{2}) \[(?P<level>\w+)\] (?P<pid>\d+)#(?P<tid>\d+): (?:\*(?P<connection>\d+) )?(?P<message>.*)$",
+            timeformat: "[year]/[month]/[day] [hour]:[minute]:[second]",
+            level_map: SYSLOG_LEVELS,
+            multiline: None,
+        },
+    ),
+    (
+        // [Wed Jan 17 13:55:30.123456 2024] [core:error] [pid 1234:tid 5678] [client 127.0.0.1:5678] AH00126: Invalid URI
+        "apache_error",
+        Preset {
+            parser: r"^\[(?P<timestamp>[^\]]+)\] \[(?:(?P<module>[^:\]]+):)?(?P<level>[^\]]+)\] \[pid (?P<pid>\d+)(?::tid (?P<tid>\d+))?\](?: \[client (?P<client>[^\]]+)\])? (?P<message>.*)$",
+            timeformat: "[weekday repr:short] [month repr:short] [day] [hour]:[minute]:[second][optional [.[subsecond]]] [year]",
+            level_map: &[
+                ("emerg", "ERROR"),
+                ("alert", "ERROR"),
+                ("crit", "ERROR"),
+                ("notice", "INFO"),
+                ("trace1", "TRACE"),
+                ("trace2", "TRACE"),
+                ("trace3", "TRACE"),
+                ("trace4", "TRACE"),
+                ("trace5", "TRACE"),
+                ("trace6", "TRACE"),
+                ("trace7", "TRACE"),
+                ("trace8", "TRACE"),
+            ],
+            multiline: None,
+        },
+    ),
+    (
+        // [2024-01-17T13:55:30Z INFO  my_crate::module] message
+        "env_logger",
+        Preset {
+            parser: r"^\[(?P<timestamp>\S+) (?P<level>[A-Z]+)\s+(?P<module>[^\]\s]+)\] (?P<message>.*)$",
+            timeformat: "rfc3339",
+            level_map: &[],
+            multiline: None,
+        },
+    ),
+    (
+        // 2024-01-17T13:55:30.123456Z  INFO request{id=1}: my_crate::module: message
+        // Spans are only recognized when they have fields
+        "tracing",
+        Preset {
+            parser: r"^(?P<timestamp>\S+)\s+(?P<level>TRACE|DEBUG|INFO|WARN|ERROR) (?:(?P<spans>[\w:]*\{.*?\}(?::[\w:]*\{.*?\})*): )?(?P<module>[\w:]+): (?P<message>.*)$",
+            timeformat: "rfc3339",
+            level_map: &[],
+            multiline: None,
+        },
+    ),
+    (
+        // WARNING:root:message, the format of logging.basicConfig()
+        "python",
+        Preset {
+            parser: r"^(?P<level>[A-Z]+):(?P<module>[^:]+):(?P<message>.*)$",
+            timeformat: "iso8601",
+            level_map: &[
+                ("CRITICAL", "ERROR"),
+                ("FATAL", "ERROR"),
+                ("WARNING", "WARN"),
+            ],
+            multiline: Some("unmatched"), // Tracebacks
+        },
+    ),
+    (
+        // 2024-01-17 13:55:30,123 - my.module - WARNING - message, with %(asctime)s - %(name)s - %(levelname)s - %(message)s
+        "python_asctime",
+        Preset {
+            parser: r"^(?P<timestamp>\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2},\d{3}) - (?P<module>\S+) - (?P<level>[A-Z]+) - (?P<message>.*)$",
+            timeformat: "[year]-[month]-[day] [hour]:[minute]:[second],[subsecond]",
+            level_map: &[
+                ("CRITICAL", "ERROR"),
+                ("FATAL", "ERROR"),
+                ("WARNING", "WARN"),
+            ],
+            multiline: Some("unmatched"), // Tracebacks
+        },
+    ),
+    (
+        // 2024-01-17 13:55:30,123 [main] INFO  com.example.App - message, with %d [%thread] %-5level %logger - %msg%n
+        "log4j",
+        Preset {
+            parser: r"^(?P<timestamp>\d{4}-\d{2}-\d{2}[ T]\d{2}:\d{2}:\d{2}[,.]\d{3}) \[(?P<thread>[^\]]+)\] (?P<level>[A-Z]+)\s+(?P<module>\S+) - (?P<message>.*)$",
+            timeformat: "[year]-[month]-[day][first [ ][T]][hour]:[minute]:[second][first [,][.]][subsecond]",
+            level_map: &[("FATAL", "ERROR")],
+            multiline: Some("unmatched"), // Stack traces
+        },
+    ),
+    (
+        // 2024-01-17 13:55:30.123 UTC [1234] LOG:  message, with the default log_line_prefix of '%m [%p] '.
+        // The timezone name is skipped, set the application's timezone if log_timezone isn't UTC.
+        "postgresql",
+        Preset {
+            parser: r"^(?P<timestamp>\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}(?:\.\d+)?) \S+ \[(?P<pid>\d+)\] (?:(?P<user>\S+)@(?P<database>\S+) )?(?P<level>[A-Z]+\d?):\s+(?P<message>.*)$",
+            timeformat: "[year]-[month]-[day] [hour]:[minute]:[second][optional [.[subsecond]]]",
+            level_map: &[
+                ("LOG", "INFO"),
+                ("STATEMENT", "INFO"),
+                ("DETAIL", "INFO"),
+                ("HINT", "INFO"),
+                ("CONTEXT", "INFO"),
+                ("QUERY", "INFO"),
+                ("LOCATION", "INFO"),
+                ("NOTICE", "INFO"),
+                ("WARNING", "WARN"),
+                ("FATAL", "ERROR"),
+                ("PANIC", "ERROR"),
+                ("DEBUG1", "DEBUG"),
+                ("DEBUG2", "DEBUG"),
+                ("DEBUG3", "DEBUG"),
+                ("DEBUG4", "DEBUG"),
+                ("DEBUG5", "DEBUG"),
+            ],
+            multiline: Some("unmatched"), // Statements spanning multiple lines
+        },
+    ),
+    (
+        // Jan 17 13:55:30 myhost sshd[1234]: message, as written to /var/log/syslog and /var/log/messages
+        "syslog",
+        Preset {
+            parser: r"^(?P<timestamp>\w{3} [ \d]\d \d{2}:\d{2}:\d{2}) (?P<hostname>\S+) (?P<module>[^\s\[:]+)(?:\[(?P<pid>\d+)\])?: (?P<message>.*)$",
+            timeformat: "[month repr:short] [day padding:space] [hour]:[minute]:[second]",
+            level_map: &[],
+            multiline: None,
+        },
+    ),
+    (
+        // 2024-01-17T13:55:30.123456+02:00 myhost sshd[1234]: message, rsyslog's high precision format
+        "syslog_rfc3339",
+        Preset {
+            parser: r"^(?P<timestamp>\S+) (?P<hostname>\S+) (?P<module>[^\s\[:]+)(?:\[(?P<pid>\d+)\])?: (?P<message>.*)$",
+            timeformat: "rfc3339",
+            level_map: &[],
+            multiline: None,
+        },
+    ),
+];
+
+// Fills in the preset's settings that the application doesn't configure itself.
+// The level maps are merged, with the application's own mappings taking precedence.
+pub fn apply_preset(app_table: &mut Map<String, Value>, preset_name: &str) -> Result<()> {
+    let preset = PRESETS
+        .iter()
+        .find(|(name, _)| *name == preset_name)
+        .map(|(_, preset)| preset)
+        .with_context(|| format!("Unknown preset: {}", preset_name))?;
+
+    let string_value = |value: &str| Value::new(None, ValueKind::String(value.to_string()));
+
+    app_table
+        .entry("parser".to_string())
+        .or_insert_with(|| string_value(preset.parser));
+    app_table
+        .entry("timeformat".to_string())
+        .or_insert_with(|| string_value(preset.timeformat));
+
+    if let Some(multiline) = preset.multiline {
+        app_table
+            .entry("multiline".to_string())
+            .or_insert_with(|| string_value(multiline));
+    }
+
+    if !preset.level_map.is_empty() {
+        let mut level_map: Map<String, Value> = preset
+            .level_map
+            .iter()
+            .map(|(level, mapped_level)| (level.to_string(), string_value(mapped_level)))
+            .collect();
+
+        if let Some(configured_map) = app_table
+            .get("level_map")
+            .and_then(|level_map| level_map.clone().into_table().ok())
+        {
+            level_map.extend(configured_map);
+        }
+
+        app_table.insert(
+            "level_map".to_string(),
+            Value::new(None, ValueKind::Table(level_map)),
+        );
+    }
+
+    Ok(())
+}
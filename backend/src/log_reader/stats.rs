@@ -0,0 +1,100 @@
+use std::collections::VecDeque;
+
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::log_reader::parser::LogParseError;
+
+// The most recent offending lines are kept so that the parser can be fixed
+const MAX_SAMPLES: usize = 10;
+const MAX_SAMPLE_LENGTH: usize = 1000;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParseErrorKind {
+    ParseFailure,
+    InvalidLevel,
+    InvalidTimestamp,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ParseErrorSample {
+    pub kind: ParseErrorKind,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub line: String,
+    pub error: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub time: OffsetDateTime,
+}
+
+// Parse errors of a single log file or other source of log lines
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ParseStats {
+    pub parse_failures: u64,
+    pub invalid_levels: u64,
+    pub timestamp_errors: u64,
+    pub samples: VecDeque<ParseErrorSample>,
+}
+
+impl ParseStats {
+    pub fn is_empty(&self) -> bool {
+        self.parse_failures == 0 && self.invalid_levels == 0 && self.timestamp_errors == 0
+    }
+
+    // Lines that couldn't be parsed, regardless of whether they were kept or dropped
+    pub fn record_error(&mut self, err: &anyhow::Error, line: &str) {
+        let kind = match err.downcast_ref() {
+            Some(LogParseError::InvalidTimestamp(_)) => ParseErrorKind::InvalidTimestamp,
+            _ => ParseErrorKind::ParseFailure,
+        };
+
+        self.record(kind, line, err.to_string());
+    }
+
+    // Lines that were parsed, but their level was replaced with INFO
+    pub fn record_invalid_level(&mut self, level: &str, line: &str) {
+        self.record(
+            ParseErrorKind::InvalidLevel,
+            line,
+            format!("Invalid log level: {}", level),
+        );
+    }
+
+    fn record(&mut self, kind: ParseErrorKind, line: &str, error: String) {
+        match kind {
+            ParseErrorKind::ParseFailure => self.parse_failures += 1,
+            ParseErrorKind::InvalidLevel => self.invalid_levels += 1,
+            ParseErrorKind::InvalidTimestamp => self.timestamp_errors += 1,
+        }
+
+        let mut sample_length = line.len().min(MAX_SAMPLE_LENGTH);
+        while !line.is_char_boundary(sample_length) {
+            sample_length -= 1;
+        }
+
+        if self.samples.len() >= MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+
+        self.samples.push_back(ParseErrorSample {
+            kind,
+            line: line[..sample_length].to_string(),
+            error,
+            time: OffsetDateTime::now_utc(),
+        });
+    }
+
+    pub fn merge(&mut self, other: ParseStats) {
+        self.parse_failures += other.parse_failures;
+        self.invalid_levels += other.invalid_levels;
+        self.timestamp_errors += other.timestamp_errors;
+
+        for sample in other.samples {
+            if self.samples.len() >= MAX_SAMPLES {
+                self.samples.pop_front();
+            }
+
+            self.samples.push_back(sample);
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::{debug, error, info};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use sysinfo::System;
+use tokio::sync::{mpsc, Mutex, RwLock};
+
+use crate::log_buffer::LogBuffer;
+use crate::log_reader::application::{
+    find_templated_applications, get_watch_paths, Application, Template,
+};
+use crate::log_reader::stats::ParseStats;
+use crate::log_reader::{load_logs, FileId, FileState};
+use crate::SETTINGS;
+
+// Changes to the server's own log file right after a reload were most likely caused by the reload's logging
+const OWN_LOG_QUIET_PERIOD: Duration = Duration::from_secs(1);
+
+// Watches the configured application paths and reads in new log entries as they are written.
+// The applications are parsed once at startup, only those of templates are added as their paths appear.
+#[allow(clippy::too_many_arguments)]
+pub async fn watch_logs(
+    buffer: Arc<RwLock<HashMap<usize, LogBuffer>>>,
+    cache: Arc<Mutex<HashMap<FileId, FileState>>>,
+    i_to_app: Arc<Mutex<HashMap<usize, String>>>,
+    parse_stats: Arc<Mutex<HashMap<usize, BTreeMap<String, ParseStats>>>>,
+    applications: Arc<Mutex<HashMap<String, Arc<Application>>>>,
+    templates: Vec<Template>,
+    sysinfo: Arc<Mutex<System>>,
+) {
+    let debounce =
+        Duration::from_millis(SETTINGS.get_int("main.watcher_debounce").unwrap_or(500) as u64);
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let own_log_dir = get_own_log_dir();
+
+    // The watcher has to be kept alive for as long as events are needed
+    let mut watcher = match notify::recommended_watcher(move |res: notify::Result<Event>| {
+        match res {
+            Ok(event) => {
+                if is_relevant(&event.kind) {
+                    let is_own_log = own_log_dir.as_ref().is_some_and(|own_log_dir| {
+                        event.paths.iter().all(|path| path.starts_with(own_log_dir))
+                    });
+
+                    // Receiver only goes away when the server is shutting down
+                    let _ = tx.send((event.paths, is_own_log));
+                }
+            }
+            Err(err) => error!("File watcher error: {}", err),
+        }
+    }) {
+        Ok(watcher) => watcher,
+        Err(err) => {
+            error!(
+                "Failed to create file watcher, log entries will not be updated! {}",
+                err
+            );
+            return;
+        }
+    };
+
+    let mut watched_paths = HashMap::new();
+    let app_list: Vec<Arc<Application>> = applications.lock().await.values().cloned().collect();
+    watch_paths(&mut watcher, &mut watched_paths, &app_list, &templates);
+
+    info!("File watcher started");
+
+    let mut reloaded_at: Option<Instant> = None;
+
+    while let Some((paths, is_own_log)) = rx.recv().await {
+        let mut changed_paths: HashSet<PathBuf> = paths.into_iter().collect();
+
+        // Give the writer some time to finish. Otherwise every reload would trigger the next one when the server's
+        // own logs are watched, so changes to them are only read once the quiet period after a reload is over.
+        let mut delay = debounce;
+        if let Some(reloaded_at) = reloaded_at.filter(|_| is_own_log) {
+            delay = delay.max(OWN_LOG_QUIET_PERIOD.saturating_sub(reloaded_at.elapsed()));
+        }
+        tokio::time::sleep(delay).await;
+
+        // Coalesce all the events that arrived meanwhile
+        while let Ok((paths, _)) = rx.try_recv() {
+            changed_paths.extend(paths);
+        }
+
+        let app_list = {
+            let mut applications = applications.lock().await;
+            let app_list: Vec<Arc<Application>> = applications.values().cloned().collect();
+
+            for app in find_templated_applications(&templates, &changed_paths, &app_list) {
+                info!("Adding application {}", app.name);
+                applications.insert(app.name.clone(), Arc::new(app));
+            }
+
+            applications.values().cloned().collect::<Vec<_>>()
+        };
+
+        load_logs(
+            buffer.clone(),
+            cache.clone(),
+            i_to_app.clone(),
+            parse_stats.clone(),
+            &app_list,
+            Some(&changed_paths),
+            sysinfo.clone(),
+            false,
+        )
+        .await;
+
+        // Files in paths that only started to be watched now may have been written to before
+        let new_paths = watch_paths(&mut watcher, &mut watched_paths, &app_list, &templates);
+        if !new_paths.is_empty() {
+            load_logs(
+                buffer.clone(),
+                cache.clone(),
+                i_to_app.clone(),
+                parse_stats.clone(),
+                &app_list,
+                Some(&new_paths),
+                sysinfo.clone(),
+                false,
+            )
+            .await;
+        }
+
+        debug!("Log entries updated");
+        reloaded_at = Some(Instant::now());
+    }
+}
+
+// Watches the paths of the applications that aren't watched yet and returns them. Paths that failed are tried again
+// on the next call, those that no longer exist are forgotten, so that they are watched again once they reappear.
+fn watch_paths(
+    watcher: &mut impl Watcher,
+    watched_paths: &mut HashMap<PathBuf, bool>,
+    applications: &[Arc<Application>],
+    templates: &[Template],
+) -> HashSet<PathBuf> {
+    let mut current_paths = HashSet::new();
+    let mut new_paths = HashSet::new();
+
+    for app_path in get_watch_paths(applications, templates) {
+        // Event paths start with the watched path, so they can be compared with the own log directory
+        let path = PathBuf::from(&app_path)
+            .canonicalize()
+            .unwrap_or_else(|_| PathBuf::from(&app_path));
+
+        // Single log files are watched through their directory so that newly created files are also noticed
+        let (watch_path, mode) = if path.is_file() {
+            (
+                path.parent()
+                    .filter(|parent| !parent.as_os_str().is_empty())
+                    .unwrap_or_else(|| Path::new("."))
+                    .to_path_buf(),
+                RecursiveMode::NonRecursive,
+            )
+        } else {
+            (path.clone(), RecursiveMode::Recursive)
+        };
+
+        current_paths.insert(watch_path.clone());
+
+        if watched_paths.get(&watch_path) == Some(&true) {
+            continue;
+        }
+
+        match watcher.watch(&watch_path, mode) {
+            Ok(_) => {
+                debug!("Watching {:?} for changes", watch_path);
+                watched_paths.insert(watch_path, true);
+                new_paths.insert(path);
+            }
+            Err(err) => {
+                // Only reported the first time
+                if watched_paths.insert(watch_path.clone(), false).is_none() {
+                    error!("Failed to watch {:?} for changes! {}", watch_path, err);
+                }
+            }
+        }
+    }
+
+    watched_paths.retain(|watch_path, is_watched| {
+        if current_paths.contains(watch_path) && (watch_path.exists() || !*is_watched) {
+            return true;
+        }
+
+        if *is_watched {
+            debug!("No longer watching {:?}", watch_path);
+            // Fails if the path was removed, which also removes the watch
+            let _ = watcher.unwatch(watch_path);
+        }
+        false
+    });
+
+    new_paths
+}
+
+// Directory the server writes its own log files to, if it does
+fn get_own_log_dir() -> Option<PathBuf> {
+    if !SETTINGS.get_bool("main.logger.log_to_file").unwrap_or(true) {
+        return None;
+    }
+
+    let log_path = SETTINGS
+        .get_string("main.logger.log_path")
+        .unwrap_or_else(|_| "logpeek-logs".to_string());
+    PathBuf::from(log_path).canonicalize().ok()
+}
+
+// Access events are ignored, otherwise reading the log files would trigger another update
+fn is_relevant(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    )
+}
@@ -1,6 +1,39 @@
-use logpeek_server::run;
+use std::process::ExitCode;
+
+use logpeek_server::{run, test_parser};
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+
+    if args.get(1).is_some_and(|arg| arg == "test-parser") {
+        // logpeek-server test-parser --config config.toml --app NAME FILE, the config path is read in config_setup
+        let mut app_name = None;
+        let mut file_path = None;
+        let mut args = args.iter().skip(2);
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => {
+                    args.next();
+                }
+                "--app" => app_name = args.next(),
+                _ => file_path = Some(arg),
+            }
+        }
+
+        return match (app_name, file_path) {
+            (Some(app_name), Some(file_path)) if test_parser(app_name, file_path) => {
+                ExitCode::SUCCESS
+            }
+            (Some(_), Some(_)) => ExitCode::FAILURE,
+            _ => {
+                eprintln!("Usage: logpeek-server test-parser [--config PATH] --app NAME FILE");
+                ExitCode::from(2)
+            }
+        };
+    }
+
     run().await;
+    ExitCode::SUCCESS
 }
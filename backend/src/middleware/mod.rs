@@ -6,9 +6,7 @@ use axum::response::Response;
 use base64::prelude::BASE64_STANDARD;
 use base64::Engine;
 use log::{debug, error, warn};
-use std::time::{Duration, SystemTime};
 
-use crate::log_reader::load_logs;
 use crate::{SharedState, SETTINGS};
 
 pub async fn authentication_middleware(
@@ -73,38 +71,3 @@ pub async fn authentication_middleware(
         Err(StatusCode::BAD_REQUEST)
     }
 }
-
-pub async fn buffer_refresh_middleware(
-    State(shared_state): State<SharedState>,
-    req: Request,
-    next: Next,
-) -> Result<Response, StatusCode> {
-    let mut last_buffer_update = shared_state.last_buffer_update.lock().await;
-    let update_cooldown = Duration::from_secs(
-        SETTINGS
-            .get_int("main.buffer_update_cooldown")
-            .unwrap_or(10) as u64,
-    );
-
-    // force-refresh header is only set by the force refresh button in the frontend
-    if req.headers().contains_key("force-refresh")
-        || last_buffer_update
-            .elapsed()
-            .unwrap_or_else(|_| Duration::from_secs(15))
-            > update_cooldown
-    {
-        load_logs(
-            shared_state.log_buffer.clone(),
-            shared_state.cache.clone(),
-            shared_state.i_to_app.clone(),
-            shared_state.sys.clone(),
-            false,
-        )
-        .await;
-        *last_buffer_update = SystemTime::now();
-
-        debug!("Log entries updated");
-    }
-
-    Ok(next.run(req).await)
-}
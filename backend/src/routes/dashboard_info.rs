@@ -5,10 +5,10 @@ use std::ops::Add;
 use axum::extract::{Query, State};
 use axum::Json;
 use log::{debug, trace};
-use ringbuffer::RingBuffer;
 use serde::{Deserialize, Serialize};
 use time::{Duration, OffsetDateTime, Time};
 
+use crate::log_buffer::MAX_MEMORY;
 use crate::{convert_app_to_i, LogBufferIterator, SharedState};
 
 #[derive(Debug, Deserialize)]
@@ -63,11 +63,11 @@ pub async fn dashboard_info_handler(
     let log_buffer_map = shared_state.log_buffer.read().await;
     let buffer_iterator = LogBufferIterator::new(&log_buffer_map, application);
 
-    for entry in
-        buffer_iterator.take_while(|entry| entry.timestamp >= start_of_tomorrow - Duration::days(7))
+    for entry in buffer_iterator
+        .take_while(|entry| entry.timestamp() >= start_of_tomorrow - Duration::days(7))
     {
-        if entry.timestamp > current_time - Duration::hours(24) {
-            let hour: usize = (current_time - entry.timestamp).whole_hours() as usize;
+        if entry.timestamp() > current_time - Duration::hours(24) {
+            let hour: usize = (current_time - entry.timestamp()).whole_hours() as usize;
             match entry.level {
                 log::Level::Error => error_logs_24[hour] += 1,
                 log::Level::Warn => warning_logs_24[hour] += 1,
@@ -81,7 +81,7 @@ pub async fn dashboard_info_handler(
                 .map(|(module, count)| (module.clone(), *count))
                 .collect();
 
-            module_count.sort_by(|a, b| b.1.cmp(&a.1));
+            module_count.sort_by_key(|entry| std::cmp::Reverse(entry.1));
 
             let total_24_errors = error_logs_24.iter().sum::<u32>();
             top_modules_24 = module_count
@@ -99,14 +99,16 @@ pub async fn dashboard_info_handler(
         }
 
         let day: usize = min(
-            (start_of_tomorrow - entry.timestamp + Duration::minutes(params.utc_offset))
+            (start_of_tomorrow - entry.timestamp() + Duration::minutes(params.utc_offset))
                 .whole_days() as usize,
             6,
         );
         match entry.level {
             log::Level::Error => {
                 error_logs_week[day] += 1;
-                *module_counter_tree.entry(entry.module.clone()).or_insert(0) += 1;
+                *module_counter_tree
+                    .entry(entry.module.to_string())
+                    .or_insert(0) += 1;
             }
             log::Level::Warn => warning_logs_week[day] += 1,
             _ => {}
@@ -120,7 +122,7 @@ pub async fn dashboard_info_handler(
         .map(|(module, count)| (module.clone(), *count))
         .collect();
 
-    module_count.sort_by(|a, b| b.1.cmp(&a.1));
+    module_count.sort_by_key(|entry| std::cmp::Reverse(entry.1));
 
     let total_week_errors = error_logs_week.iter().sum::<u32>();
     let top_modules_week: Vec<(String, f32)> = module_count
@@ -135,16 +137,23 @@ pub async fn dashboard_info_handler(
     }
 
     let mut total_length = 0;
-    let mut total_capacity = 0;
+    let mut memory_usage: usize = 0;
+    let mut memory_limit: usize = 0;
 
     log_buffer_map
         .iter()
         .filter(|entry| application.is_none() || application.unwrap() == *entry.0)
         .for_each(|entry| {
             total_length += entry.1.len();
-            total_capacity += entry.1.capacity();
+            memory_usage += entry.1.memory_usage();
+            memory_limit = memory_limit.saturating_add(entry.1.memory_limit());
         });
 
+    // The buffers of all applications share main.max_memory
+    if let Some(max_memory) = *MAX_MEMORY {
+        memory_limit = memory_limit.min(max_memory);
+    }
+
     Json(DashboardResponse {
         total_logs_24,
         error_logs_24,
@@ -154,7 +163,7 @@ pub async fn dashboard_info_handler(
         warning_logs_week,
         top_modules_24,
         top_modules_week,
-        log_buffer_usage: total_length as f32 / total_capacity as f32 * 100.0,
+        log_buffer_usage: memory_usage as f32 / memory_limit as f32 * 100.0,
         total_log_entries: total_length as u32,
     })
 }
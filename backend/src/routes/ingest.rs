@@ -0,0 +1,193 @@
+use std::collections::BTreeMap;
+
+use axum::body::Bytes;
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::Json;
+use log::{error, trace};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::log_buffer::enforce_max_memory;
+use crate::log_reader::stats::ParseStats;
+use crate::log_reader::{journal, process_line, LineSource, LogFormat};
+use crate::{convert_app_to_i, LogEntry, SharedState};
+
+// Ingested lines continue the entries of earlier requests to the same application
+const INGEST_SOURCE: u64 = u64::MAX;
+
+// Pre-structured entry, as sent with an NDJSON body
+#[derive(Debug, Deserialize)]
+struct IngestEntry {
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    timestamp: Option<OffsetDateTime>,
+    level: Option<log::Level>,
+    module: Option<String>,
+    message: String,
+    #[serde(default)]
+    fields: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IngestResponse {
+    pub accepted: usize,
+    pub rejected: usize,
+}
+
+// Plain text bodies go through the application's configured parser line by line,
+// NDJSON bodies (application/x-ndjson) contain one LogEntry shaped object per line.
+// Applications using the journal export format take the output of `journalctl -o export` as is.
+pub async fn ingest_handler(
+    Path(application): Path<String>,
+    State(shared_state): State<SharedState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<IngestResponse>, StatusCode> {
+    trace!("Ingest request received for {}", application);
+
+    let app = shared_state
+        .applications
+        .lock()
+        .await
+        .get(&application)
+        .cloned()
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let i_to_app = shared_state.i_to_app.lock().await;
+    let app_i = convert_app_to_i(&application, &i_to_app).ok_or(StatusCode::NOT_FOUND)?;
+
+    let is_ndjson = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/x-ndjson"));
+
+    let mut log_buffer_map = shared_state.log_buffer.write().await;
+    let log_buffer = log_buffer_map
+        .get_mut(&app_i)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut accepted = 0;
+    let mut rejected = 0;
+    let mut stats = ParseStats::default();
+
+    if matches!(app.format, LogFormat::JournalExport) {
+        let mut body = body.to_vec();
+        body.extend_from_slice(b"\n\n"); // The last entry doesn't have to be terminated
+        let mut reader = body.as_slice();
+
+        loop {
+            match journal::read_export_entry(&mut reader) {
+                Ok(Some((_, journal_fields))) => {
+                    match journal::build_entry(journal_fields, app_i) {
+                        Ok(entry) => {
+                            log_buffer.push(entry);
+                            accepted += 1;
+                        }
+                        Err(err) => {
+                            error!("Failed to ingest entry for {}! {}", application, err);
+                            stats.record_error(&err, "");
+                            rejected += 1;
+                        }
+                    }
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    error!("Failed to ingest entry for {}! {}", application, err);
+                    log_buffer.commit(accepted);
+                    enforce_max_memory(&mut log_buffer_map);
+                    record_stats(&shared_state, app_i, stats).await;
+                    return Err(StatusCode::BAD_REQUEST);
+                }
+            }
+        }
+
+        log_buffer.commit(accepted);
+        enforce_max_memory(&mut log_buffer_map);
+        record_stats(&shared_state, app_i, stats).await;
+        return Ok(Json(IngestResponse { accepted, rejected }));
+    }
+
+    let body = std::str::from_utf8(&body).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let mut source = LineSource::new(INGEST_SOURCE);
+    let mut partial_lines = 0;
+
+    for line in body.lines() {
+        let line = line.trim_end_matches('\r');
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let result = if is_ndjson {
+            serde_json::from_str::<IngestEntry>(line)
+                .map(|entry| {
+                    log_buffer.push_from(
+                        INGEST_SOURCE,
+                        LogEntry {
+                            timestamp: entry.timestamp.unwrap_or_else(OffsetDateTime::now_utc),
+                            level: entry.level.unwrap_or(log::Level::Info),
+                            module: entry.module.unwrap_or_else(|| "N/A".to_string()),
+                            message: entry.message,
+                            fields: entry.fields,
+                            application: app_i,
+                            parse_failed: false,
+                        },
+                    )
+                })
+                .map_err(|err| {
+                    let err = anyhow::anyhow!("{} in line: {}", err, line);
+                    stats.record_error(&err, line);
+                    err
+                })
+        } else {
+            process_line(line, &app, app_i, &mut source, log_buffer, &mut stats)
+        };
+
+        match result {
+            // Parts of a container message only count once the message is complete
+            Ok(_) if source.has_partial_messages() => partial_lines += 1,
+            Ok(_) => {
+                accepted += 1 + partial_lines;
+                partial_lines = 0;
+            }
+            Err(err) => {
+                error!("Failed to ingest line for {}! {}", application, err);
+                rejected += 1;
+
+                if !source.has_partial_messages() {
+                    rejected += partial_lines;
+                    partial_lines = 0;
+                }
+            }
+        }
+    }
+
+    // The rest of a partial container message would only arrive with the next request, which may never happen
+    if source.has_partial_messages() {
+        let err = anyhow::anyhow!("Incomplete container message at the end of the body");
+        error!("Failed to ingest line for {}! {}", application, err);
+        stats.record_error(&err, "");
+        rejected += partial_lines;
+    }
+
+    // Entries with their own timestamps don't have to arrive in order
+    log_buffer.commit(accepted);
+    enforce_max_memory(&mut log_buffer_map);
+    record_stats(&shared_state, app_i, stats).await;
+    Ok(Json(IngestResponse { accepted, rejected }))
+}
+
+// Ingested lines don't belong to a file, so their parse errors are grouped under "ingest"
+async fn record_stats(shared_state: &SharedState, app_i: usize, stats: ParseStats) {
+    if !stats.is_empty() {
+        shared_state
+            .parse_stats
+            .lock()
+            .await
+            .entry(app_i)
+            .or_default()
+            .entry("ingest".to_string())
+            .or_default()
+            .merge(stats);
+    }
+}
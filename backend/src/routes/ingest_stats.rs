@@ -0,0 +1,47 @@
+use std::collections::BTreeMap;
+
+use axum::extract::State;
+use axum::Json;
+use log::trace;
+use serde::Serialize;
+
+use crate::log_reader::stats::ParseStats;
+use crate::SharedState;
+
+#[derive(Debug, Serialize)]
+pub struct ApplicationStats {
+    pub parse_failures: u64,
+    pub invalid_levels: u64,
+    pub timestamp_errors: u64,
+    // Keyed by file path, lines sent to the ingest endpoint are under "ingest"
+    pub files: BTreeMap<String, ParseStats>,
+}
+
+// Parse errors of every application, so that misconfigured parsers can be noticed without reading the server's own log
+pub async fn ingest_stats_handler(
+    State(shared_state): State<SharedState>,
+) -> Json<BTreeMap<String, ApplicationStats>> {
+    trace!("Request received");
+
+    let i_to_app = shared_state.i_to_app.lock().await;
+    let parse_stats = shared_state.parse_stats.lock().await;
+
+    let app_stats = i_to_app
+        .iter()
+        .map(|(app_i, app_name)| {
+            let files = parse_stats.get(app_i).cloned().unwrap_or_default();
+
+            (
+                app_name.clone(),
+                ApplicationStats {
+                    parse_failures: files.values().map(|stats| stats.parse_failures).sum(),
+                    invalid_levels: files.values().map(|stats| stats.invalid_levels).sum(),
+                    timestamp_errors: files.values().map(|stats| stats.timestamp_errors).sum(),
+                    files,
+                },
+            )
+        })
+        .collect();
+
+    Json(app_stats)
+}
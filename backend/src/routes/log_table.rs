@@ -8,6 +8,7 @@ use log::{error, trace};
 use serde::{de, Deserialize, Deserializer, Serialize};
 use time::OffsetDateTime;
 
+use crate::log_buffer::LogEntryRef;
 use crate::{convert_app_to_i, LogBufferIterator, LogEntry, SharedState};
 
 #[derive(Debug, Deserialize)]
@@ -25,6 +26,8 @@ pub struct Params {
     #[serde(default, deserialize_with = "empty_string_as_none")]
     end_timestamp: Option<String>,
     application: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    parse_failed: Option<bool>,
 }
 
 #[derive(Debug, Serialize)]
@@ -48,11 +51,13 @@ struct LogFilter {
     message: Option<regex::Regex>,
     start_timestamp: Option<OffsetDateTime>,
     end_timestamp: Option<OffsetDateTime>,
+    fields: Vec<(String, regex::Regex)>,
+    parse_failed: Option<bool>,
     is_passtrough: bool,
 }
 
 impl LogFilter {
-    async fn new(params: Params) -> Result<Self> {
+    async fn new(params: Params, raw_params: Vec<(String, String)>) -> Result<Self> {
         let Params {
             page,
             items_per_page,
@@ -62,6 +67,7 @@ impl LogFilter {
             start_timestamp,
             end_timestamp,
             application: _,
+            parse_failed,
         } = params;
 
         let index = (page - 1) * items_per_page;
@@ -96,11 +102,23 @@ impl LogFilter {
             })
             .transpose()?;
 
+        // Field filters are given as field.<key>=<regex>
+        let fields = raw_params
+            .into_iter()
+            .filter_map(|(key, value)| {
+                key.strip_prefix("field.")
+                    .filter(|_| !value.is_empty())
+                    .map(|field| Ok((field.to_string(), regex::Regex::new(&value)?)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
         // All possible entries will match the filter
         let is_passtrough = module_name.is_none()
             && message.is_none()
             && start_timestamp.is_none()
-            && end_timestamp.is_none();
+            && end_timestamp.is_none()
+            && fields.is_empty()
+            && parse_failed.is_none();
 
         Ok(Self {
             index,
@@ -110,10 +128,12 @@ impl LogFilter {
             message,
             start_timestamp,
             end_timestamp,
+            fields,
+            parse_failed,
             is_passtrough,
         })
     }
-    fn matches(&self, entry: &LogEntry) -> bool {
+    fn matches(&self, entry: &LogEntryRef) -> bool {
         if self.is_passtrough {
             return true;
         }
@@ -125,35 +145,49 @@ impl LogFilter {
         }
 
         if let Some(module_name) = &self.module_name {
-            if !module_name.is_match(&entry.module) {
+            if !module_name.is_match(entry.module) {
                 return false;
             }
         }
 
         if let Some(message) = &self.message {
-            if !message.is_match(&entry.message) {
+            if !message.is_match(entry.message) {
                 return false;
             }
         }
 
         if let Some(start_timestamp) = &self.start_timestamp {
-            if entry.timestamp < *start_timestamp {
+            if entry.timestamp() < *start_timestamp {
                 return false;
             }
         }
 
         if let Some(end_timestamp) = &self.end_timestamp {
-            if entry.timestamp > *end_timestamp {
+            if entry.timestamp() > *end_timestamp {
+                return false;
+            }
+        }
+
+        if let Some(parse_failed) = self.parse_failed {
+            if entry.parse_failed != parse_failed {
                 return false;
             }
         }
 
+        for (field, field_filter) in &self.fields {
+            match entry.field(field) {
+                Some(value) if field_filter.is_match(value) => {}
+                _ => return false,
+            }
+        }
+
         true
     }
 }
 
 pub async fn log_table_handler(
     Query(params): Query<Params>,
+    Query(raw_params): Query<Vec<(String, String)>>,
     State(shared_state): State<SharedState>,
 ) -> (StatusCode, Json<LogTableResponse>) {
     trace!("Request received {:?}", &params);
@@ -165,7 +199,7 @@ pub async fn log_table_handler(
         None
     };
 
-    let log_filter_result = LogFilter::new(params).await;
+    let log_filter_result = LogFilter::new(params, raw_params).await;
 
     match log_filter_result {
         Ok(log_filter) => {
@@ -178,14 +212,14 @@ pub async fn log_table_handler(
             let mut total_items: usize = 0;
 
             for entry in buffer_iterator {
-                if log_filter.matches(entry) {
+                if log_filter.matches(&entry) {
                     total_items += 1;
 
                     if skipped < log_filter.index {
                         skipped += 1;
                     } else if taken < log_filter.items_per_page {
                         result.push(LogEntryWithApplication {
-                            entry: entry.clone(),
+                            entry: entry.to_entry(),
                             application: i_to_app.get(&entry.application).unwrap().clone(),
                         });
                         taken += 1;
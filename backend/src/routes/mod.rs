@@ -1,5 +1,6 @@
 use axum::middleware::from_fn_with_state;
-use axum::{routing::get, Router};
+use axum::routing::{get, post};
+use axum::Router;
 use dashboard_info::dashboard_info_handler;
 use log::info;
 use log_table::log_table_handler;
@@ -8,12 +9,18 @@ use sysinfo::sysinfo_handler;
 
 use crate::routes::application_list::application_list_handler;
 use crate::routes::authenticate::authenticate_handler;
+use crate::routes::ingest::ingest_handler;
+use crate::routes::ingest_stats::ingest_stats_handler;
+use crate::routes::parser_test::parser_test_handler;
 use crate::{middleware, SharedState, SETTINGS};
 
 mod application_list;
 mod authenticate;
 mod dashboard_info;
+mod ingest;
+mod ingest_stats;
 mod log_table;
+mod parser_test;
 mod sysinfo;
 
 pub async fn router_setup(shared_state: SharedState) -> Router {
@@ -21,12 +28,11 @@ pub async fn router_setup(shared_state: SharedState) -> Router {
         .route("/api/dashboard_info", get(dashboard_info_handler))
         .route("/api/log_table", get(log_table_handler))
         .route("/api/authenticate", get(authenticate_handler))
-        .layer(from_fn_with_state(
-            shared_state.clone(),
-            middleware::buffer_refresh_middleware,
-        ))
         .route("/api/sysinfo", get(sysinfo_handler))
         .route("/api/application_list", get(application_list_handler))
+        .route("/api/ingest/:application", post(ingest_handler))
+        .route("/api/ingest_stats", get(ingest_stats_handler))
+        .route("/api/parser_test", post(parser_test_handler))
         .with_state(shared_state.clone());
 
     if !SETTINGS
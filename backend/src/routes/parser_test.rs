@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use config::{Value, ValueKind};
+use log::trace;
+use serde::Deserialize;
+
+use crate::log_reader::application::Application;
+use crate::log_reader::parser_test::{test_parser, ParserTestResult};
+use crate::SharedState;
+
+#[derive(Debug, Deserialize)]
+pub struct ParserTestRequest {
+    // Name of a configured application
+    application: Option<String>,
+    // Or an application to try out, with the same fields as an [[application]] section of the config file
+    config: Option<Value>,
+    input: String,
+}
+
+pub async fn parser_test_handler(
+    State(shared_state): State<SharedState>,
+    Json(request): Json<ParserTestRequest>,
+) -> Result<Json<ParserTestResult>, (StatusCode, String)> {
+    trace!("Request received");
+
+    let app = match (request.config, request.application) {
+        (Some(mut config), _) => {
+            if let ValueKind::Table(table) = &mut config.kind {
+                table
+                    .entry("name".to_string())
+                    .or_insert_with(|| Value::new(None, ValueKind::String("parser_test".into())));
+            }
+
+            Application::from_config(config)
+                .map(Arc::new)
+                .map_err(|err| (StatusCode::BAD_REQUEST, format!("{:#}", err)))?
+        }
+        (None, Some(application)) => shared_state
+            .applications
+            .lock()
+            .await
+            .get(&application)
+            .cloned()
+            .ok_or_else(|| {
+                (
+                    StatusCode::NOT_FOUND,
+                    format!("Unknown application: {}", application),
+                )
+            })?,
+        (None, None) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Either application or config is required".to_string(),
+            ))
+        }
+    };
+
+    Ok(Json(test_parser(&request.input, &app)))
+}
@@ -4,6 +4,8 @@ export interface LogEntry {
   level: string;
   module: string;
   message: string;
+  fields?: Record<string, string>;
+  parse_failed?: boolean;
 }
 
 export interface LogEntryWithApplication {